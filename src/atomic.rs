@@ -0,0 +1,62 @@
+//! Crash-safe file writes
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Write `contents` to `path` atomically
+///
+/// The contents go to a temporary file next to `path` first, which then replaces `path` by
+/// renaming, so an interrupted write never leaves a truncated file behind.
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    write_with(path, |file| file.write_all(contents.as_ref()))
+}
+
+/// Write the file at `path` atomically with `write`, like [`write`], for contents too large to
+/// hold in memory
+pub fn write_with<E: From<io::Error>>(
+    path: &Path,
+    write: impl FnOnce(&mut File) -> Result<(), E>,
+) -> Result<(), E> {
+    let temporary = temporary_path(path);
+    let result = write_temporary(&temporary, path, write).and_then(|()| {
+        fs::rename(&temporary, path)?;
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+/// Copy the file at `path` to `<path>.bak`, if it exists
+pub fn backup(path: &Path) -> io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    let contents = fs::read(path)?;
+    write(Path::new(&backup), contents)
+}
+
+fn write_temporary<E: From<io::Error>>(
+    temporary: &Path,
+    path: &Path,
+    write: impl FnOnce(&mut File) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut file = File::create(temporary)?;
+    write(&mut file)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.sync_all()?;
+    Ok(())
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.{}.tmp", std::process::id()))
+}
@@ -0,0 +1,226 @@
+//! Generation and check throughput over a synthetic manifest
+
+use crate::{
+    check::{self, Target},
+    generate::Generators,
+    hash,
+    manifest::{song_url, Manifest, Song},
+    probe,
+};
+use anyhow::Context;
+use std::{
+    fmt,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// The measurements of a benchmark run
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub tracks: usize,
+    pub rounds: usize,
+    /// The total time spent generating all formats, over all rounds
+    pub generate: Duration,
+    /// The total size of one round of generated artifacts, in bytes
+    pub output: usize,
+    /// The time spent checking all tracks against a local mock server
+    pub check: Duration,
+    /// The time spent hashing a synthetic file per track one at a time, and with `jobs`
+    pub hash_sequential: Duration,
+    pub hash_parallel: Duration,
+    pub jobs: usize,
+    /// The peak resident memory of the process in KiB, where the OS reports it
+    pub peak_memory: Option<u64>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let per_second = |count: usize, time: Duration| count as f64 / time.as_secs_f64();
+        writeln!(
+            f,
+            "generate: {} tracks x {} rounds in {:.3}s ({:.0} tracks/s, {} bytes per round)",
+            self.tracks,
+            self.rounds,
+            self.generate.as_secs_f64(),
+            per_second(self.tracks * self.rounds, self.generate),
+            self.output
+        )?;
+        writeln!(
+            f,
+            "check: {} tracks in {:.3}s ({:.0} tracks/s)",
+            self.tracks,
+            self.check.as_secs_f64(),
+            per_second(self.tracks, self.check)
+        )?;
+        writeln!(
+            f,
+            "hash: {} files in {:.3}s sequentially, {:.3}s with {} jobs ({:.1}x)",
+            self.tracks,
+            self.hash_sequential.as_secs_f64(),
+            self.hash_parallel.as_secs_f64(),
+            self.jobs,
+            self.hash_sequential.as_secs_f64() / self.hash_parallel.as_secs_f64()
+        )?;
+        match self.peak_memory {
+            Some(kib) => write!(f, "peak memory: {kib} KiB"),
+            None => write!(f, "peak memory: unknown"),
+        }
+    }
+}
+
+/// A manifest of `tracks` synthetic songs
+pub fn synthetic(tracks: usize) -> Manifest {
+    Manifest {
+        title: "Benchmark".to_string(),
+        url: Some("https://example.com/".to_string()),
+        songs: (0..tracks)
+            .map(|i| Song {
+                name: format!("Track {i}"),
+                path: format!("track-{i:05}.mp3").into(),
+                duration: Some(180.0 + (i % 120) as f64),
+                size: Some(4_000_000 + i as u64),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Generate all builtin formats for `tracks` songs `rounds` times, then check them all
+pub async fn run(tracks: usize, rounds: usize) -> anyhow::Result<Report> {
+    let mut manifest = synthetic(tracks);
+    let generators = Generators::builtin("index.html", ".");
+    // The synthetic songs have no local files to archive
+    let names = generators
+        .names()
+        .filter(|name| *name != "zip")
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    let urls = manifest
+        .songs
+        .iter()
+        .map(|song| song_url(&manifest, song))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut output = 0;
+    let start = Instant::now();
+    for _ in 0..rounds {
+        let artifacts = generators.generate(&names, &manifest, &urls)?;
+        output = 0;
+        for artifact in artifacts {
+            output += artifact.contents.into_bytes()?.len();
+        }
+    }
+    let generate = start.elapsed();
+
+    let address = mock_server().await?;
+    manifest.prefix = format!("http://{address}/");
+    let targets = manifest
+        .songs
+        .iter()
+        .map(|song| {
+            let url = song_url(&manifest, song)?;
+            Ok(Target::Url {
+                location: url.clone(),
+                url,
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let start = Instant::now();
+    let responses =
+        check::check_all(targets, check::Method::Get, &Default::default(), None).await?;
+    let check = start.elapsed();
+    if let Some(failed) = responses.iter().find(|r| !r.status().is_success()) {
+        anyhow::bail!("Mock check failed: {failed}");
+    }
+
+    let jobs = probe::default_jobs();
+    let (hash_sequential, hash_parallel) = hash_files(tracks, jobs).await?;
+
+    Ok(Report {
+        tracks,
+        rounds,
+        generate,
+        output,
+        check,
+        hash_sequential,
+        hash_parallel,
+        jobs,
+        peak_memory: peak_memory(),
+    })
+}
+
+/// The time spent hashing `files` synthetic files one at a time, and `jobs` at a time
+async fn hash_files(files: usize, jobs: usize) -> anyhow::Result<(Duration, Duration)> {
+    let directory =
+        std::env::temp_dir().join(format!("trackinator-benchmark-{}", std::process::id()));
+    let times = time_hashing(&directory, files, jobs).await;
+    let _ = std::fs::remove_dir_all(&directory);
+    times
+}
+
+/// [`hash_files`] in `directory`, leaving the files behind
+async fn time_hashing(
+    directory: &Path,
+    files: usize,
+    jobs: usize,
+) -> anyhow::Result<(Duration, Duration)> {
+    std::fs::create_dir_all(directory)
+        .with_context(|| format!("Failed to create {}", directory.display()))?;
+    let paths = (0..files)
+        .map(|i| {
+            let path = directory.join(format!("track-{i:05}.mp3"));
+            std::fs::write(&path, vec![i as u8; 256 * 1024])?;
+            Ok(path)
+        })
+        .collect::<std::io::Result<Vec<_>>>()
+        .context("Failed to write benchmark files")?;
+    let time = |jobs| {
+        let paths = paths.clone();
+        async move {
+            let start = Instant::now();
+            for hashed in probe::probe_all(paths, jobs, "Hashing", hash::sha256_file).await? {
+                hashed?;
+            }
+            anyhow::Ok(start.elapsed())
+        }
+    };
+    Ok((time(1).await?, time(jobs).await?))
+}
+
+/// Serve `200 OK` to every request on a local port, returning its address
+async fn mock_server() -> anyhow::Result<std::net::SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .context("Failed to bind mock server")?;
+    let address = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buffer = [0; 4096];
+                // One request per connection is enough for the checker
+                if stream.read(&mut buffer).await.is_ok() {
+                    let _ = stream
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        )
+                        .await;
+                }
+            });
+        }
+    });
+    Ok(address)
+}
+
+/// The peak resident set size from `/proc/self/status`, in KiB
+fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+}
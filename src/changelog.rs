@@ -0,0 +1,89 @@
+//! The tracks added, removed and renamed between two versions of a manifest
+
+use anyhow::{bail, Context};
+use std::{collections::HashMap, fmt, path::Path, process};
+
+use crate::manifest::{Manifest, Song, Storage};
+
+/// A difference between two versions of a manifest
+#[derive(Debug, Clone)]
+pub enum Change {
+    Added(Song),
+    Removed(Song),
+    /// A song at the same location with a different name
+    Renamed {
+        from: String,
+        song: Song,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added(song) => write!(f, "+ {}", song.name),
+            Change::Removed(song) => write!(f, "- {}", song.name),
+            Change::Renamed { from, song } => write!(f, "~ {from} -> {}", song.name),
+        }
+    }
+}
+
+/// The changes from `old` to `new`, matching songs by their prefix and path
+///
+/// Added and renamed songs are in the order of `new`, followed by the removed ones in the
+/// order of `old`.
+pub fn changes(old: &Manifest, new: &Manifest) -> Vec<Change> {
+    let location = |manifest: &Manifest, song: &Song| {
+        let prefix = song.prefix.as_deref().unwrap_or(&manifest.prefix);
+        format!("{prefix}{}", song.path.display())
+    };
+    let mut before = HashMap::<String, Vec<&Song>>::new();
+    for song in old.songs.iter().rev() {
+        before.entry(location(old, song)).or_default().push(song);
+    }
+    let mut changes = Vec::new();
+    for song in &new.songs {
+        match before.get_mut(&location(new, song)).and_then(Vec::pop) {
+            Some(previous) if previous.name != song.name => changes.push(Change::Renamed {
+                from: previous.name.clone(),
+                song: song.clone(),
+            }),
+            Some(_) => {}
+            None => changes.push(Change::Added(song.clone())),
+        }
+    }
+    for song in &old.songs {
+        if let Some(remaining) = before.get_mut(&location(old, song)) {
+            if remaining
+                .last()
+                .is_some_and(|remaining| std::ptr::eq(*remaining, song))
+            {
+                remaining.pop();
+                changes.push(Change::Removed(song.clone()));
+            }
+        }
+    }
+    changes
+}
+
+/// The manifest `manifest` as of the git revision `revision`, like `HEAD~1` or `v1.0`
+pub fn at_revision(manifest: &Path, revision: &str) -> anyhow::Result<(Manifest, Storage)> {
+    let directory = manifest.parent().unwrap_or(Path::new(""));
+    let name = manifest.file_name().context("Invalid manifest file name")?;
+    let mut command = process::Command::new("git");
+    if !directory.as_os_str().is_empty() {
+        command.arg("-C").arg(directory);
+    }
+    let output = command
+        .arg("show")
+        .arg(format!("{revision}:./{}", name.to_string_lossy()))
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to read {} at {revision}: {}",
+            manifest.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Manifest::parse(output.stdout, false)
+}
@@ -0,0 +1,516 @@
+//! Reachability checks of song URLs
+
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Duration, Utc};
+use futures::{StreamExt, TryStreamExt};
+use lychee_lib::{Base, ClientBuilder, Collector, Input, InputSource, Response, Status, Uri};
+use reqwest::{header::RANGE, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, path::Path, str::FromStr};
+use url::Url;
+
+use crate::{http, manifest::decode_path, progress::Progress};
+
+/// A URL to check
+#[derive(Debug, Clone)]
+pub enum Target {
+    /// Checked by lychee, or by a HEAD or range request, at `location`, reported as `url`
+    ///
+    /// The `location` may carry access tokens, which are kept out of the output this way.
+    Url { url: String, location: String },
+    /// Checked by a HEAD request to the `presigned` URL, reported as `url`
+    Presigned { url: String, presigned: String },
+}
+
+/// How to request `http` and `https` URLs when checking them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Method {
+    /// A HEAD request, falling back to GET if the server rejects HEAD
+    Head,
+    /// A GET request for the first byte only
+    Range,
+    /// A full check by lychee
+    #[default]
+    Get,
+}
+
+impl FromStr for Method {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "head" => Ok(Method::Head),
+            "range" => Ok(Method::Range),
+            "get" => Ok(Method::Get),
+            _ => bail!("Unknown method {s}, expected one of head, range, get"),
+        }
+    }
+}
+
+/// The class of a check result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    Ok,
+    /// The request failed without a response, e.g. for DNS or TLS errors or missing files
+    Network,
+    /// The request timed out
+    Timeout,
+    /// The server answered with a 4xx status
+    Broken,
+    /// The server answered with a 5xx status
+    ServerError,
+}
+
+impl Outcome {
+    pub fn of(status: &Status) -> Self {
+        if status.is_success() {
+            return Outcome::Ok;
+        }
+        if let Status::Timeout(_) = status {
+            return Outcome::Timeout;
+        }
+        match status.code() {
+            Some(code) if code.is_client_error() => Outcome::Broken,
+            Some(code) if code.is_server_error() => Outcome::ServerError,
+            _ => Outcome::Network,
+        }
+    }
+
+    /// The exit code of `check` for this outcome: 3 for network errors and timeouts, 4 for
+    /// 4xx and 5 for 5xx statuses
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Ok => 0,
+            Outcome::Network | Outcome::Timeout => 3,
+            Outcome::Broken => 4,
+            Outcome::ServerError => 5,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Network => "network error",
+            Outcome::Timeout => "timeout",
+            Outcome::Broken => "broken",
+            Outcome::ServerError => "server error",
+        }
+    }
+}
+
+/// Why a check failed, as far as can be told from its status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Failure {
+    Dns,
+    Tls,
+    Refused,
+    Timeout,
+    Forbidden,
+    NotFound,
+    Client(u16),
+    Server(u16),
+    MissingFile,
+    Other,
+}
+
+impl Failure {
+    /// The failure of `status`, or `None` if it succeeded
+    pub fn of(status: &Status) -> Option<Self> {
+        if status.is_success() {
+            return None;
+        }
+        if let Status::Timeout(_) = status {
+            return Some(Failure::Timeout);
+        }
+        if let Some(code) = status.code() {
+            return Some(match code.as_u16() {
+                403 => Failure::Forbidden,
+                404 | 410 => Failure::NotFound,
+                code @ 400..=499 => Failure::Client(code),
+                code @ 500..=599 => Failure::Server(code),
+                _ => Failure::Other,
+            });
+        }
+        let Status::Error(error) = status else {
+            return Some(Failure::Other);
+        };
+        // The underlying causes are only exposed as text
+        let mut text = format!("{error} {error:?}");
+        let mut source = std::error::Error::source(error);
+        while let Some(error) = source {
+            text.push_str(&format!(" {error}"));
+            source = error.source();
+        }
+        let text = text.to_lowercase();
+        let failure = if text.contains("dns error") || text.contains("failed to lookup address") {
+            Failure::Dns
+        } else if ["certificate", "tls", "ssl", "handshake"]
+            .iter()
+            .any(|needle| text.contains(needle))
+        {
+            Failure::Tls
+        } else if text.contains("connection refused") {
+            Failure::Refused
+        } else if text.contains("timed out") {
+            Failure::Timeout
+        } else if text.contains("cannot find file") || text.contains("invalid file path") {
+            Failure::MissingFile
+        } else {
+            Failure::Other
+        };
+        Some(failure)
+    }
+
+    /// A likely cause and what to do about it
+    pub fn hint(self) -> &'static str {
+        match self {
+            Failure::Dns => "the host name does not resolve; check the prefix for typos or the DNS records of the domain",
+            Failure::Tls => "the TLS handshake failed; check the certificate of the host, e.g. for expiry or a name mismatch",
+            Failure::Refused => "nothing listens on this host and port; check the server is running and the port is right",
+            Failure::Timeout => "the host is slow or unreachable; check for outages or firewalls and retry later",
+            Failure::Forbidden => "access is denied; check the object permissions, bucket policy or hotlink protection",
+            Failure::NotFound => "the files are missing at this location; check the prefix and paths or redeploy the media",
+            Failure::Client(_) => "the server rejected the request; check the URLs and any required authentication",
+            Failure::Server(_) => "the server failed; check its logs or the status page of the provider and retry later",
+            Failure::MissingFile => "the local files are missing; check the paths relative to the manifest",
+            Failure::Other => "the request failed; rerun with -v for the individual errors",
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Dns => f.write_str("DNS failure"),
+            Failure::Tls => f.write_str("TLS error"),
+            Failure::Refused => f.write_str("connection refused"),
+            Failure::Timeout => f.write_str("timeout"),
+            Failure::Forbidden => f.write_str("forbidden (403)"),
+            Failure::NotFound => f.write_str("not found (404)"),
+            Failure::Client(code) => write!(f, "client error ({code})"),
+            Failure::Server(code) => write!(f, "server error ({code})"),
+            Failure::MissingFile => f.write_str("missing file"),
+            Failure::Other => f.write_str("other error"),
+        }
+    }
+}
+
+/// Failures grouped by host, with hints on their causes
+#[derive(Debug, Clone, Default)]
+pub struct HostReport {
+    hosts: BTreeMap<String, BTreeMap<Failure, usize>>,
+}
+
+impl HostReport {
+    /// Count the failure of `response`, if any
+    pub fn add(&mut self, response: &Response) {
+        let Some(failure) = Failure::of(response.status()) else {
+            return;
+        };
+        let uri = response.1.uri.as_str();
+        let host = match Url::parse(uri) {
+            Ok(url) if url.scheme() == "file" => "local files".to_string(),
+            Ok(url) => match (url.host_str(), url.port()) {
+                (Some(host), Some(port)) => format!("{host}:{port}"),
+                (Some(host), None) => host.to_string(),
+                (None, _) => uri.to_string(),
+            },
+            Err(_) => uri.to_string(),
+        };
+        *self
+            .hosts
+            .entry(host)
+            .or_default()
+            .entry(failure)
+            .or_default() += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+}
+
+impl fmt::Display for HostReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (host, failures) in &self.hosts {
+            writeln!(f, "{host}:")?;
+            for (failure, count) in failures {
+                writeln!(f, "  {count} {failure}: {}", failure.hint())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The number of results per outcome, displayed like `17 ok, 2 broken, 1 timeout`
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    counts: BTreeMap<Outcome, usize>,
+    /// Files whose recorded size or hash differs
+    pub drifted: usize,
+    /// Local files included in the page, like lyrics, that do not exist
+    pub missing: usize,
+    /// Targets left unchecked after too many failures
+    pub skipped: usize,
+    /// Targets skipped since they were checked recently
+    pub cached: usize,
+}
+
+impl Summary {
+    pub fn add(&mut self, outcome: Outcome) {
+        *self.counts.entry(outcome).or_default() += 1;
+    }
+
+    /// The exit code of `check`: that of the worst outcome, else 1 if any file drifted or is
+    /// missing
+    pub fn exit_code(&self) -> i32 {
+        match self.counts.keys().map(|outcome| outcome.exit_code()).max() {
+            Some(code) if code > 0 => code,
+            _ => i32::from(self.drifted > 0 || self.missing > 0),
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ok", self.counts.get(&Outcome::Ok).unwrap_or(&0))?;
+        for (outcome, count) in &self.counts {
+            if *outcome != Outcome::Ok {
+                write!(f, ", {count} {}", outcome.label())?;
+            }
+        }
+        if self.drifted > 0 {
+            write!(f, ", {} drifted", self.drifted)?;
+        }
+        if self.missing > 0 {
+            write!(f, ", {} missing", self.missing)?;
+        }
+        if self.skipped > 0 {
+            write!(f, ", {} skipped", self.skipped)?;
+        }
+        if self.cached > 0 {
+            write!(f, ", {} cached", self.cached)?;
+        }
+        Ok(())
+    }
+}
+
+/// The name of the check cache file, next to the manifest
+pub const CACHE_FILE: &str = ".trackinator-check.json";
+
+/// The last check result per URL
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Cache {
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct CacheEntry {
+    status: String,
+    ok: bool,
+    /// Whether the recorded size and hash matched as well
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    verified: bool,
+    checked_at: DateTime<Utc>,
+}
+
+impl Cache {
+    /// Load the cache at `path`, empty if there is none yet
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = match std::fs::read(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).context("Failed to open check cache"),
+        };
+        match serde_json::from_slice(&json) {
+            Ok(cache) => Ok(cache),
+            Err(e) => {
+                tracing::warn!("Ignoring invalid check cache {}: {e}", path.display());
+                Ok(Self::default())
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize check cache")?;
+        crate::atomic::write(path, json).context("Failed to write check cache")
+    }
+
+    /// Whether `url` was found reachable, and also `verified` if requested, within `max_age`
+    pub fn is_fresh(
+        &self,
+        url: &str,
+        max_age: Duration,
+        verified: bool,
+        now: DateTime<Utc>,
+    ) -> bool {
+        self.entries.get(url).is_some_and(|entry| {
+            entry.ok && (entry.verified || !verified) && now - entry.checked_at <= max_age
+        })
+    }
+
+    /// Record `status` as the result of checking `url` at `now`
+    pub fn record(&mut self, url: &str, status: &Status, now: DateTime<Utc>) {
+        let entry = CacheEntry {
+            status: status.to_string(),
+            ok: status.is_success(),
+            verified: false,
+            checked_at: now,
+        };
+        self.entries.insert(url.to_string(), entry);
+    }
+
+    /// Record that `url` matched its recorded size and hash
+    pub fn mark_verified(&mut self, url: &str) {
+        if let Some(entry) = self.entries.get_mut(url) {
+            entry.verified = true;
+        }
+    }
+}
+
+/// Check all `targets` concurrently with `method`, returning their responses in order
+///
+/// With `max_failures`, checking stops once that many targets failed, in order, and only the
+/// responses up to the last failure are returned. Local files are always checked by lychee.
+/// Requests are sent with the headers and credentials of `options`, except to presigned URLs.
+pub async fn check_all(
+    targets: Vec<Target>,
+    method: Method,
+    options: &http::Options,
+    max_failures: Option<usize>,
+) -> anyhow::Result<Vec<Response>> {
+    let checker = options.checker()?;
+    let client = options.client()?;
+    let presigned_client = options.without_auth().client()?;
+    let mut handles = targets
+        .into_iter()
+        .map(|target| match target {
+            Target::Url { url, location } if method == Method::Get || !url.starts_with("http") => {
+                let checker = checker.clone();
+                tokio::spawn(async move {
+                    let mut response = checker.check(location).await?;
+                    response.1.uri = Uri::try_from(url.as_str())?;
+                    response.0 = InputSource::String(url);
+                    Ok(response)
+                })
+            }
+            Target::Url { url, location } => {
+                tokio::spawn(check_http(client.clone(), url, location, method))
+            }
+            Target::Presigned { url, presigned } => tokio::spawn(check_http(
+                presigned_client.clone(),
+                url,
+                presigned,
+                Method::Head,
+            )),
+        })
+        .collect::<Vec<_>>()
+        .into_iter();
+    let progress = Progress::new(handles.len(), "Checking", "URLs")?;
+    let mut responses = Vec::new();
+    let mut failures = 0;
+    while let Some(handle) = handles.next() {
+        let response = handle
+            .await
+            .context("Failed to join the check tasks")?
+            .context("Resource unreachable")?;
+        if !response.status().is_success() {
+            failures += 1;
+        }
+        progress.done(format!("{} {}", response.status(), response.0));
+        responses.push(response);
+        if max_failures.is_some_and(|max| failures >= max) {
+            handles.for_each(|handle| handle.abort());
+            break;
+        }
+    }
+    progress.finish();
+    Ok(responses)
+}
+
+/// Check every link of the generated `page`, like `src`, `href` and asset references
+///
+/// Relative links are resolved against `base` if given, else against the page location.
+pub async fn check_page(page: &Path, base: Option<Url>) -> anyhow::Result<Vec<Response>> {
+    let path = page.to_str().context("Page path is not valid UTF-8")?;
+    let input = Input::new(path, None, false, None)
+        .with_context(|| format!("Invalid page {}", page.display()))?;
+    let requests = Collector::new(base.map(Base::Remote))
+        .collect_links(vec![input])
+        .await
+        .try_collect::<Vec<_>>()
+        .await
+        .with_context(|| format!("Failed to collect links of {}", page.display()))?;
+    let client = ClientBuilder::default()
+        .client()
+        .context("Failed to build link checker")?;
+    let client = &client;
+    futures::stream::iter(requests)
+        .map(|request| async move {
+            tracing::debug!("Checking {}", redact(&request.uri));
+            client.check(request).await
+        })
+        .buffered(16)
+        .try_collect()
+        .await
+        .context("Resource unreachable")
+}
+
+/// `uri` without its query and fragment, which may carry access tokens, for output
+pub fn redact(uri: &Uri) -> String {
+    match Url::parse(uri.as_str()) {
+        Ok(mut url) => {
+            url.set_query(None);
+            url.set_fragment(None);
+            url.to_string()
+        }
+        Err(_) => uri.to_string(),
+    }
+}
+
+/// The URL to check for a song URL, resolving local paths against `base` into `file://` URLs
+pub fn check_url(url: String, base: &Path) -> anyhow::Result<String> {
+    if Url::parse(&url).is_ok() {
+        return Ok(url);
+    }
+    let path = base.join(decode_path(&url)?);
+    let file = Url::from_file_path(&path)
+        .map_err(|()| anyhow!("Invalid local path {}", path.display()))?;
+    Ok(file.to_string())
+}
+
+/// Check the object at `url` by a HEAD or range request to `location`, reported as `url`
+///
+/// Servers rejecting HEAD requests, or ranges of empty files, are asked with a plain GET,
+/// without downloading the body.
+async fn check_http(
+    client: reqwest::Client,
+    url: String,
+    location: String,
+    method: Method,
+) -> lychee_lib::Result<Response> {
+    let uri = Uri::try_from(url.as_str())?;
+    let request = match method {
+        Method::Head => client.head(&location),
+        Method::Range => client.get(&location).header(RANGE, "bytes=0-0"),
+        Method::Get => client.get(&location),
+    };
+    let mut result = request.send().await;
+    let rejected = [
+        StatusCode::METHOD_NOT_ALLOWED,
+        StatusCode::NOT_IMPLEMENTED,
+        StatusCode::RANGE_NOT_SATISFIABLE,
+    ];
+    if let Ok(response) = &result {
+        if rejected.contains(&response.status()) {
+            tracing::debug!("{url} rejected the request with {}", response.status());
+            result = client.get(&location).send().await;
+        }
+    }
+    let status = match result {
+        Ok(response) => Status::new(&response, None),
+        Err(error) => error.without_url().into(),
+    };
+    Ok(Response::new(uri, status, InputSource::String(url)))
+}
@@ -0,0 +1,148 @@
+use anyhow::{bail, Context};
+use reqwest::{header::CONTENT_LENGTH, Client};
+use sha2::{Digest, Sha256};
+use std::{fmt, path::Path};
+use url::Url;
+
+use crate::hash::{hex, sha256_file};
+
+/// How the hosted copy of a track differs from its local master
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    /// The sizes differ
+    Size { local: u64, remote: u64 },
+    /// The sizes match, but the SHA-256 hashes differ
+    Hash { local: String, remote: String },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::Size { local, remote } => {
+                write!(
+                    f,
+                    "size differs: local {local} bytes, remote {remote} bytes"
+                )
+            }
+            Drift::Hash { local, remote } => {
+                write!(f, "hash differs: local {local}, remote {remote}")
+            }
+        }
+    }
+}
+
+/// Compare the local file at `local` to the copy hosted at `url`
+///
+/// Only sizes are compared unless `hash` is set or the server does not announce a
+/// `Content-Length`, in which case the remote copy is downloaded.
+pub async fn compare(
+    client: &Client,
+    local: &Path,
+    url: &str,
+    hash: bool,
+) -> anyhow::Result<Option<Drift>> {
+    let local_size = std::fs::metadata(local)
+        .with_context(|| format!("Failed to read {}", local.display()))?
+        .len();
+
+    if !hash {
+        if let Some(remote) = remote_size(client, url).await? {
+            return Ok((remote != local_size).then_some(Drift::Size {
+                local: local_size,
+                remote,
+            }));
+        }
+    }
+
+    let (remote_size, remote_hash) = download_hash(client, url).await?;
+    if remote_size != local_size {
+        return Ok(Some(Drift::Size {
+            local: local_size,
+            remote: remote_size,
+        }));
+    }
+    let local_hash = sha256_file(local)?;
+    Ok((local_hash != remote_hash).then_some(Drift::Hash {
+        local: local_hash,
+        remote: remote_hash,
+    }))
+}
+
+/// Verify the copy at `url` against the recorded `size` and SHA-256 `hash` of its master
+///
+/// The copy is downloaded if a hash is given, otherwise only its `Content-Length` is compared.
+/// `file://` URLs are verified against the local file.
+pub async fn verify(
+    client: &Client,
+    url: &str,
+    size: Option<u64>,
+    hash: Option<&str>,
+) -> anyhow::Result<Option<Drift>> {
+    let file = Url::parse(url)
+        .ok()
+        .filter(|url| url.scheme() == "file")
+        .and_then(|url| url.to_file_path().ok());
+    let (remote_size, remote_hash) = match (&file, hash) {
+        (Some(file), Some(_)) => (Some(crate::probe::size(file)?), Some(sha256_file(file)?)),
+        (Some(file), None) => (Some(crate::probe::size(file)?), None),
+        (None, Some(_)) => {
+            let (size, hash) = download_hash(client, url).await?;
+            (Some(size), Some(hash))
+        }
+        (None, None) => (remote_size(client, url).await?, None),
+    };
+    if let (Some(local), Some(remote)) = (size, remote_size) {
+        if local != remote {
+            return Ok(Some(Drift::Size { local, remote }));
+        }
+    }
+    if let (Some(local), Some(remote)) = (hash, remote_hash) {
+        if local != remote {
+            return Ok(Some(Drift::Hash {
+                local: local.to_string(),
+                remote,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// The `Content-Length` announced for `url` in response to a HEAD request
+async fn remote_size(client: &Client, url: &str) -> anyhow::Result<Option<u64>> {
+    let response = client
+        .head(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(reqwest::Error::without_url)
+        .context("Failed to request remote copy")?;
+    Ok(response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse().ok()))
+}
+
+/// Download `url`, returning its size and hex-encoded SHA-256 hash
+pub async fn download_hash(client: &Client, url: &str) -> anyhow::Result<(u64, String)> {
+    let mut response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(reqwest::Error::without_url)
+        .context("Failed to request remote copy")?;
+    if !response.status().is_success() {
+        bail!("Remote copy unavailable: {}", response.status());
+    }
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(reqwest::Error::without_url)
+        .context("Failed to download remote copy")?
+    {
+        size += chunk.len() as u64;
+        hasher.update(&chunk);
+    }
+    Ok((size, hex(&hasher.finalize())))
+}
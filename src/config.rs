@@ -0,0 +1,84 @@
+//! Project defaults from `trackinator.toml`
+
+use anyhow::Context;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::notify;
+
+/// The name of the config file, looked up in the working directory and its ancestors
+pub const FILE: &str = "trackinator.toml";
+
+/// The environment variable overriding the config file location
+pub const CONFIG_VAR: &str = "TRACKINATOR_CONFIG";
+
+/// Defaults for command line arguments
+///
+/// Relative paths are resolved against the directory of the config file.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The default `--manifest`
+    pub manifest: Option<PathBuf>,
+    /// The default `--output` of `generate` and `deploy`
+    pub output: Option<PathBuf>,
+    /// The default `--prefix` of `template`
+    pub prefix: Option<String>,
+    /// Whether to commit manifest changes to git, like `--commit`
+    #[serde(default)]
+    pub commit: bool,
+    /// The manifests of the workspace, checked together by `lint`
+    #[serde(default)]
+    pub workspace: Vec<PathBuf>,
+    /// Defaults for `check`
+    #[serde(default)]
+    pub check: Check,
+    /// Where to send notifications about `check` failures and deploys
+    #[serde(default)]
+    pub notify: Vec<notify::Backend>,
+}
+
+/// Defaults for `check`
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Check {
+    /// The default `--verify`
+    pub verify: Option<bool>,
+}
+
+impl Config {
+    /// Load the config from `$TRACKINATOR_CONFIG`, or else the nearest `trackinator.toml`
+    ///
+    /// Without a config file, all defaults are unset.
+    pub fn discover() -> anyhow::Result<Self> {
+        if let Some(path) = std::env::var_os(CONFIG_VAR).filter(|path| !path.is_empty()) {
+            return Self::load(Path::new(&path));
+        }
+        let cwd = std::env::current_dir().context("Failed to get working directory")?;
+        match cwd
+            .ancestors()
+            .map(|dir| dir.join(FILE))
+            .find(|path| path.is_file())
+        {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Load the config file at `path`
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to open config {}", path.display()))?;
+        let mut config: Self = toml::from_str(&text)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        for path in [&mut config.manifest, &mut config.output]
+            .into_iter()
+            .flatten()
+            .chain(&mut config.workspace)
+        {
+            *path = base.join(&*path);
+        }
+        Ok(config)
+    }
+}
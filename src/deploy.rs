@@ -0,0 +1,427 @@
+//! Uploading generated pages and media to rsync, SFTP and S3 targets, resumable by checkpoint
+
+use anyhow::{anyhow, bail, Context};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    str::FromStr,
+    time::UNIX_EPOCH,
+};
+use url::Url;
+
+use crate::{atomic, serve::Rate};
+
+/// The name of the file recording deploy progress, next to the manifest
+pub const CHECKPOINT_FILE: &str = ".trackinator-deploy.json";
+
+/// How many files rsync and sftp upload per run, between checkpoints
+const BATCH: usize = 32;
+
+/// Where to upload the generated page and media to
+#[derive(Debug, Clone)]
+pub enum Target {
+    /// Anything `rsync` accepts as destination, e.g. `host:path` or a local directory
+    Rsync(String),
+    /// An `sftp://[user@]host[:port]/path` URL
+    Sftp(Url),
+    /// An `s3://bucket/key-prefix` URL, uploaded with the `aws` CLI
+    S3(String),
+}
+
+impl FromStr for Target {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("s3://") {
+            Ok(Target::S3(s.trim_end_matches('/').to_string()))
+        } else if s.starts_with("sftp://") {
+            let url = Url::parse(s).context("Invalid SFTP target")?;
+            if url.host_str().is_none() {
+                bail!("SFTP target {s} has no host");
+            }
+            Ok(Target::Sftp(url))
+        } else if s.is_empty() {
+            bail!("Empty deploy target")
+        } else {
+            Ok(Target::Rsync(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Rsync(destination) => f.write_str(destination),
+            Target::Sftp(url) => write!(f, "{url}"),
+            Target::S3(prefix) => f.write_str(prefix),
+        }
+    }
+}
+
+/// A single file to upload
+#[derive(Debug, Clone)]
+pub struct Upload {
+    /// The directory `remote` is relative to locally
+    pub base: PathBuf,
+    /// The `/`-separated path relative to both `base` and the target root
+    pub remote: String,
+}
+
+impl Upload {
+    fn local(&self) -> PathBuf {
+        self.base.join(&self.remote)
+    }
+
+    /// The size and modification time of the local file
+    fn stamp(&self) -> anyhow::Result<Stamp> {
+        let local = self.local();
+        let metadata = std::fs::metadata(&local)
+            .with_context(|| format!("Cannot upload missing file {}", local.display()))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |modified| modified.as_secs());
+        Ok(Stamp {
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// How to deploy
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// Only show what would be transferred
+    pub dry_run: bool,
+    /// Upload no faster than this
+    pub bwlimit: Option<Rate>,
+    /// Stop before uploading more than this many bytes
+    pub quota: Option<u64>,
+}
+
+/// What a deploy did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Deployed {
+    /// The files uploaded
+    pub uploaded: usize,
+    /// The files skipped as already uploaded by an interrupted deploy
+    pub skipped: usize,
+    /// The files left for later by the quota
+    pub remaining: usize,
+}
+
+/// A local file as it was uploaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+struct Stamp {
+    size: u64,
+    modified: u64,
+}
+
+/// The files uploaded to a target so far, to resume an interrupted deploy
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Checkpoint {
+    #[serde(skip)]
+    path: PathBuf,
+    target: String,
+    uploaded: BTreeMap<String, Stamp>,
+}
+
+impl Checkpoint {
+    /// An empty checkpoint for `target`, saved to `path`
+    pub fn new(path: impl Into<PathBuf>, target: &Target) -> Self {
+        Self {
+            path: path.into(),
+            target: target.to_string(),
+            uploaded: BTreeMap::new(),
+        }
+    }
+
+    /// The checkpoint at `path` if it is for `target`, or else an empty one
+    pub fn load(path: impl Into<PathBuf>, target: &Target) -> anyhow::Result<Self> {
+        let path = path.into();
+        let checkpoint = match std::fs::read(&path) {
+            Ok(contents) => serde_json::from_slice::<Self>(&contents)
+                .with_context(|| format!("Failed to read checkpoint {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to open checkpoint {}", path.display()))
+            }
+        };
+        if checkpoint.target != target.to_string() {
+            return Ok(Self::new(path, target));
+        }
+        Ok(Self { path, ..checkpoint })
+    }
+
+    fn is_uploaded(&self, upload: &Upload, stamp: Stamp) -> bool {
+        self.uploaded.get(&upload.remote) == Some(&stamp)
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let contents = serde_json::to_vec_pretty(self).context("Failed to serialize checkpoint")?;
+        atomic::write(&self.path, contents)
+            .with_context(|| format!("Failed to write checkpoint {}", self.path.display()))
+    }
+}
+
+/// Upload `uploads` to `target`, skipping those unchanged since recorded in `checkpoint`
+///
+/// Progress is saved to the checkpoint after every batch of files, so that an interrupted
+/// deploy can continue where it stopped. With a `quota`, files are uploaded in order while
+/// they fit in what is left of it, and those that don't are counted as remaining.
+pub fn deploy(
+    target: &Target,
+    uploads: &[Upload],
+    options: Options,
+    checkpoint: &mut Checkpoint,
+) -> anyhow::Result<Deployed> {
+    if options.bwlimit.is_some() && matches!(target, Target::S3(_)) {
+        bail!("The aws CLI cannot limit bandwidth per call, set s3.max_bandwidth in its config");
+    }
+    let mut deployed = Deployed::default();
+    let mut pending = Vec::new();
+    let mut budget = options.quota;
+    for upload in uploads {
+        let stamp = upload.stamp()?;
+        if checkpoint.is_uploaded(upload, stamp) {
+            deployed.skipped += 1;
+            continue;
+        }
+        if let Some(budget) = &mut budget {
+            if stamp.size > *budget {
+                deployed.remaining += 1;
+                continue;
+            }
+            *budget -= stamp.size;
+        }
+        pending.push((upload.clone(), stamp));
+    }
+    let batch = match target {
+        Target::S3(_) => 1,
+        _ => BATCH,
+    };
+    for chunk in pending.chunks(batch) {
+        let uploads = chunk
+            .iter()
+            .map(|(upload, _)| upload.clone())
+            .collect::<Vec<_>>();
+        match target {
+            Target::Rsync(destination) => rsync(destination, &uploads, options)?,
+            Target::Sftp(url) => sftp(url, &uploads, options)?,
+            Target::S3(prefix) => s3(prefix, &uploads, options)?,
+        }
+        deployed.uploaded += chunk.len();
+        if !options.dry_run {
+            for (upload, stamp) in chunk {
+                checkpoint.uploaded.insert(upload.remote.clone(), *stamp);
+            }
+            checkpoint.save()?;
+        }
+    }
+    Ok(deployed)
+}
+
+fn rsync(destination: &str, uploads: &[Upload], options: Options) -> anyhow::Result<()> {
+    let mut command = Command::new("rsync");
+    // Keep partially transferred files, so that a retry continues them
+    command.args(["--archive", "--relative", "--verbose", "--partial"]);
+    if options.dry_run {
+        command.arg("--dry-run");
+    }
+    if let Some(rate) = options.bwlimit {
+        // In KiB per second
+        let limit = (rate.bytes_per_second() / 1024.0).ceil().max(1.0);
+        command.arg(format!("--bwlimit={limit}"));
+    }
+    // The `/./` marker makes `--relative` recreate only the part after it on the target
+    command.args(
+        uploads
+            .iter()
+            .map(|upload| upload.base.join(".").join(&upload.remote)),
+    );
+    command.arg(destination);
+    run(&mut command, None)
+}
+
+fn sftp(url: &Url, uploads: &[Upload], options: Options) -> anyhow::Result<()> {
+    let root = decode(url.path())?;
+    let root = root.trim_end_matches('/');
+    let mut batch = String::new();
+    let mut directories = Vec::new();
+    for upload in uploads {
+        let remote = format!("{root}/{}", upload.remote);
+        for (index, _) in remote.match_indices('/').skip(1) {
+            let directory = &remote[..index];
+            if !directories.iter().any(|d| d == directory) {
+                directories.push(directory.to_string());
+                // A leading `-` makes sftp ignore failures, e.g. for existing directories
+                batch.push_str(&format!("-mkdir {}\n", quote(directory)));
+            }
+        }
+        batch.push_str(&format!(
+            "put {} {}\n",
+            quote(&upload.local().to_string_lossy()),
+            quote(&remote)
+        ));
+    }
+    if options.dry_run {
+        print!("{batch}");
+        return Ok(());
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("SFTP target has no host"))?;
+    let destination = match &*decode(url.username())? {
+        "" => host.to_string(),
+        user => format!("{user}@{host}"),
+    };
+    let mut command = Command::new("sftp");
+    command.args(["-b", "-"]);
+    if let Some(rate) = options.bwlimit {
+        // In Kbit per second
+        let limit = (rate.bytes_per_second() * 8.0 / 1000.0).ceil().max(1.0);
+        command.args(["-l", &limit.to_string()]);
+    }
+    if let Some(port) = url.port() {
+        command.args(["-P", &port.to_string()]);
+    }
+    command.arg(destination);
+    run(&mut command, Some(&batch))
+}
+
+/// The percent-encoded part `s` of an SFTP URL, decoded
+fn decode(s: &str) -> anyhow::Result<std::borrow::Cow<'_, str>> {
+    percent_decode_str(s)
+        .decode_utf8()
+        .with_context(|| format!("SFTP target {s} is not valid UTF-8"))
+}
+
+/// `path` quoted for an sftp batch, with `"` and `\` escaped by a backslash
+fn quote(path: &str) -> String {
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn s3(prefix: &str, uploads: &[Upload], options: Options) -> anyhow::Result<()> {
+    for upload in uploads {
+        let mut command = Command::new("aws");
+        command.args(["s3", "cp"]);
+        if options.dry_run {
+            command.arg("--dryrun");
+        }
+        command
+            .arg(upload.local())
+            .arg(format!("{prefix}/{}", upload.remote));
+        run(&mut command, None)?;
+    }
+    Ok(())
+}
+
+/// Run `command` to completion, feeding it `input` on stdin if given
+fn run(command: &mut Command, input: Option<&str>) -> anyhow::Result<()> {
+    let program = command.get_program().to_string_lossy().to_string();
+    if input.is_some() {
+        command.stdin(Stdio::piped());
+    }
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Failed to run {program}"))?;
+    if let Some(input) = input {
+        let mut stdin = child.stdin.take().context("Failed to open stdin")?;
+        stdin
+            .write_all(input.as_bytes())
+            .with_context(|| format!("Failed to write to {program}"))?;
+    }
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for {program}"))?;
+    if !status.success() {
+        bail!("{program} failed with {status}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Uploads of new files of `sizes` in a fresh directory named after `test`
+    fn uploads(test: &str, sizes: &[usize]) -> Vec<Upload> {
+        let base =
+            std::env::temp_dir().join(format!("trackinator-deploy-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base).unwrap();
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(index, &size)| {
+                let remote = format!("{index}.mp3");
+                std::fs::write(base.join(&remote), vec![0; size]).unwrap();
+                Upload {
+                    base: base.clone(),
+                    remote,
+                }
+            })
+            .collect()
+    }
+
+    fn sftp() -> Target {
+        // Dry runs to SFTP targets print the batch without running sftp
+        "sftp://example.com/music".parse().unwrap()
+    }
+
+    fn dry_run(quota: Option<u64>) -> Options {
+        Options {
+            dry_run: true,
+            quota,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn files_exceeding_the_quota_remain() {
+        let uploads = uploads("quota", &[10, 50, 20, 15]);
+        let mut checkpoint = Checkpoint::new("unused.json", &sftp());
+        let deployed = deploy(&sftp(), &uploads, dry_run(Some(40)), &mut checkpoint).unwrap();
+        let expected = Deployed {
+            uploaded: 2,
+            skipped: 0,
+            remaining: 2,
+        };
+        assert_eq!(deployed, expected);
+        let deployed = deploy(&sftp(), &uploads, dry_run(None), &mut checkpoint).unwrap();
+        assert_eq!(deployed.uploaded, 4);
+    }
+
+    #[test]
+    fn checkpoints_skip_unchanged_files() {
+        let uploads = uploads("checkpoint", &[10, 20]);
+        let path = uploads[0].base.join(CHECKPOINT_FILE);
+        let mut checkpoint = Checkpoint::new(&path, &sftp());
+        let stamp = uploads[0].stamp().unwrap();
+        checkpoint.uploaded.insert(uploads[0].remote.clone(), stamp);
+        let changed = Stamp {
+            size: 1,
+            ..uploads[1].stamp().unwrap()
+        };
+        checkpoint
+            .uploaded
+            .insert(uploads[1].remote.clone(), changed);
+        checkpoint.save().unwrap();
+
+        let mut checkpoint = Checkpoint::load(&path, &sftp()).unwrap();
+        let deployed = deploy(&sftp(), &uploads, dry_run(None), &mut checkpoint).unwrap();
+        assert_eq!((deployed.skipped, deployed.uploaded), (1, 1));
+
+        let other: Target = "sftp://example.com/other".parse().unwrap();
+        let checkpoint = Checkpoint::load(&path, &other).unwrap();
+        assert!(checkpoint.uploaded.is_empty());
+        assert_eq!(checkpoint.target, other.to_string());
+    }
+}
@@ -0,0 +1,150 @@
+//! Mirroring remote songs into a local directory
+
+use anyhow::{bail, Context};
+use futures::StreamExt;
+use indicatif::MultiProgress;
+use reqwest::{header::RANGE, Client, StatusCode};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+use tokio::io::AsyncWriteExt;
+
+use crate::progress::{self, Progress};
+
+/// A song to download from `url` to `path`
+#[derive(Debug, Clone)]
+pub struct Download {
+    pub url: String,
+    pub path: PathBuf,
+}
+
+/// Download all `downloads` with `client`, at most `jobs` at a time, with a progress bar per
+/// download
+///
+/// Files that already exist are skipped. Interrupted downloads are kept next to their target
+/// with a `.part` extension and resumed where they stopped, if the server supports ranges.
+/// Songs sharing a path are downloaded once. All downloads are attempted, the first failure is
+/// returned afterwards.
+pub async fn download_all(
+    mut downloads: Vec<Download>,
+    client: &Client,
+    jobs: usize,
+) -> anyhow::Result<()> {
+    let mut paths = HashSet::new();
+    downloads.retain(|download| paths.insert(download.path.clone()));
+    let progress = progress::multi();
+    let total = Progress::add_to(&progress, downloads.len(), "Downloading", "songs")?;
+    let results = futures::stream::iter(downloads)
+        .map(|download| {
+            let (progress, total) = (&progress, &total);
+            async move {
+                let result = download_one(client, &download, progress, total).await;
+                let path = download.path.display();
+                match &result {
+                    Ok(()) => total.done(path),
+                    Err(e) => {
+                        progress.suspend(|| {
+                            tracing::error!("Failed to download {}: {e:#}", download.url)
+                        });
+                        total.done(format!("{path} failed"));
+                    }
+                }
+                result
+            }
+        })
+        .buffer_unordered(jobs.max(1))
+        .collect::<Vec<_>>()
+        .await;
+    total.finish();
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    match results.into_iter().find_map(Result::err) {
+        Some(e) => Err(e.context(format!("{failed} downloads failed"))),
+        None => Ok(()),
+    }
+}
+
+/// Download `download`, resuming a partial download if there is one
+async fn download_one(
+    client: &Client,
+    download: &Download,
+    progress: &MultiProgress,
+    total: &Progress,
+) -> anyhow::Result<()> {
+    if download.path.exists() {
+        tracing::info!("Skipping existing {}", download.path.display());
+        return Ok(());
+    }
+    if let Some(parent) = download.path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let part = part_path(&download.path);
+    let offset = tokio::fs::metadata(&part).await.map_or(0, |m| m.len());
+
+    let mut request = client.get(&download.url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={offset}-"));
+    }
+    let mut response = request.send().await.context("Failed to request song")?;
+    let resumed = match response.status() {
+        StatusCode::PARTIAL_CONTENT => true,
+        // The partial download already is the complete file
+        StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+            return finish(&part, &download.path).await;
+        }
+        status if status.is_success() => false,
+        status => bail!("Server responded with {status}"),
+    };
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(&part)
+        .await
+        .with_context(|| format!("Failed to open {}", part.display()))?;
+    let start = if resumed { offset } else { 0 };
+    let bar = progress::bytes(
+        progress,
+        response.content_length().map_or(0, |length| start + length),
+    )?;
+    bar.set_prefix(if resumed { "Resuming" } else { "Fetching" });
+    bar.set_message(
+        download
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    );
+    bar.set_position(start);
+
+    while let Some(chunk) = response.chunk().await.context("Failed to download song")? {
+        file.write_all(&chunk)
+            .await
+            .with_context(|| format!("Failed to write {}", part.display()))?;
+        bar.inc(chunk.len() as u64);
+        total.add_bytes(chunk.len() as u64);
+    }
+    file.flush().await?;
+    bar.finish_and_clear();
+    finish(&part, &download.path).await
+}
+
+/// Move the completed download at `part` to `path`
+async fn finish(part: &Path, path: &Path) -> anyhow::Result<()> {
+    tokio::fs::rename(part, path)
+        .await
+        .with_context(|| format!("Failed to move download to {}", path.display()))?;
+    tracing::info!("Downloaded {}", path.display());
+    Ok(())
+}
+
+/// Where the partial download of `path` is kept
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
@@ -0,0 +1,81 @@
+//! Problems of individual tracks, which `generate --skip-invalid` leaves out instead of failing
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::manifest::{plain_song_url, Manifest};
+
+/// What is wrong with a track
+#[derive(Debug)]
+pub enum TrackErrorKind {
+    /// Its path cannot be turned into a URL, like a path that is not valid UTF-8
+    InvalidPath(PathBuf),
+    /// A file included in its page, like its synced lyrics, cannot be read as text
+    UnreadableFile { path: PathBuf, source: io::Error },
+}
+
+/// A track that cannot be published
+#[derive(Debug)]
+pub struct TrackError {
+    /// The index of the track in the checked manifest
+    pub index: usize,
+    /// The ID of the track, to refer to it in other commands
+    pub id: String,
+    pub name: String,
+    pub kind: TrackErrorKind,
+}
+
+impl fmt::Display for TrackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): ", self.name, self.id)?;
+        match &self.kind {
+            TrackErrorKind::InvalidPath(path) => {
+                write!(f, "path {} cannot be made a URL", path.display())
+            }
+            TrackErrorKind::UnreadableFile { path, source } => {
+                write!(f, "cannot read {}: {source}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for TrackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            TrackErrorKind::InvalidPath(_) => None,
+            TrackErrorKind::UnreadableFile { source, .. } => Some(source),
+        }
+    }
+}
+
+/// The tracks of `manifest` that cannot be published, checking the files included in their
+/// pages below `base` as well if `files`
+pub fn invalid_tracks(manifest: &Manifest, base: &Path, files: bool) -> Vec<TrackError> {
+    let mut errors = Vec::new();
+    let ids = manifest.slugs();
+    for (index, song) in manifest.songs.iter().enumerate() {
+        let error = |kind| TrackError {
+            index,
+            id: ids[index].clone(),
+            name: song.name.clone(),
+            kind,
+        };
+        if plain_song_url(manifest, song).is_err() {
+            errors.push(error(TrackErrorKind::InvalidPath(song.path.clone())));
+            continue;
+        }
+        if !files {
+            continue;
+        }
+        for path in song.files() {
+            if let Err(source) = std::fs::read_to_string(base.join(path)) {
+                let path = path.to_path_buf();
+                errors.push(error(TrackErrorKind::UnreadableFile { path, source }));
+                break;
+            }
+        }
+    }
+    errors
+}
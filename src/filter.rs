@@ -0,0 +1,88 @@
+//! Selecting songs by expressions like `tag=live` or `album!=Demos`
+
+use anyhow::{bail, Context};
+use std::str::FromStr;
+
+use crate::manifest::{Manifest, Song};
+
+/// The song field a [`Filter`] tests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Tag,
+    Album,
+    Artist,
+    Series,
+    Season,
+}
+
+/// A condition on songs, parsed from `key=value` or `key!=value`
+///
+/// The keys are `tag`, `album`, `artist` (the track artist, or else the album artist),
+/// `series` and `season`. Values are compared exactly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub key: Key,
+    pub value: String,
+    /// Match the songs that do not fulfill the condition instead
+    pub negated: bool,
+}
+
+impl Filter {
+    /// The filter matching the songs whose `key` is `value`
+    pub fn new(key: Key, value: impl Into<String>) -> Self {
+        Self {
+            key,
+            value: value.into(),
+            negated: false,
+        }
+    }
+
+    /// Whether `song` of `manifest` matches this filter
+    pub fn matches(&self, manifest: &Manifest, song: &Song) -> bool {
+        let value = self.value.as_str();
+        let matches = match self.key {
+            Key::Tag => song.tags.iter().any(|tag| tag == value),
+            Key::Album => song.album.as_deref() == Some(value),
+            Key::Artist => manifest.artist_of(song) == Some(value),
+            Key::Series => song.series.as_deref() == Some(value),
+            Key::Season => song
+                .season
+                .is_some_and(|season| season.to_string() == value),
+        };
+        matches != self.negated
+    }
+}
+
+/// Whether `song` of `manifest` matches all `filters`
+pub fn matches_all(filters: &[Filter], manifest: &Manifest, song: &Song) -> bool {
+    filters.iter().all(|filter| filter.matches(manifest, song))
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value, negated) = match s.split_once("!=") {
+            Some((key, value)) => (key, value, true),
+            None => {
+                let (key, value) = s
+                    .split_once('=')
+                    .with_context(|| format!("Filter {s} is not like key=value"))?;
+                (key, value, false)
+            }
+        };
+        let key = match key.trim() {
+            "tag" => Key::Tag,
+            "album" => Key::Album,
+            "artist" => Key::Artist,
+            "series" => Key::Series,
+            "season" => Key::Season,
+            key => bail!("Unknown filter key {key}, expected tag, album, artist, series or season"),
+        };
+        Ok(Self {
+            key,
+            value: value.trim().to_string(),
+            negated,
+        })
+    }
+}
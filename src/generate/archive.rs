@@ -0,0 +1,136 @@
+use anyhow::Context;
+use std::{collections::HashSet, fmt::Write as _, fs::File, io, path::PathBuf};
+use url::Url;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use super::{Artifact, Contents, Generator, Sink};
+use crate::{
+    hash::sha256_file,
+    manifest::{decode_path, slugify, Manifest},
+};
+
+/// The name of the checksums file next to the archives
+pub const CHECKSUMS: &str = "SHA256SUMS";
+
+/// One ZIP of the local audio files per album, plus a `SHA256SUMS` file covering them
+///
+/// Songs without album go into an archive named after the manifest title, if any. Inside the
+/// archives, files are named after the songs, including their track numbers.
+///
+/// The archives are [streamed](Contents::Stream) when written, to not hold the audio in memory.
+#[derive(Debug, Clone)]
+pub struct Archives {
+    base: PathBuf,
+}
+
+impl Archives {
+    /// Read the local audio files relative to `base`
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Self { base: base.into() }
+    }
+}
+
+/// The songs of an album that are local files, archived together
+#[derive(Debug, Clone)]
+pub(crate) struct Album {
+    pub name: String,
+    /// The file name of the archive
+    pub file: String,
+    /// The indices of the songs and their paths relative to the manifest
+    pub songs: Vec<(usize, PathBuf)>,
+}
+
+/// The albums of `manifest` with local songs, in order of first appearance
+///
+/// Archive names are suffixed with `-2`, `-3`, … where album names slugify the same.
+pub(crate) fn albums(manifest: &Manifest, urls: &[String]) -> anyhow::Result<Vec<Album>> {
+    let mut albums: Vec<Album> = Vec::new();
+    let mut files = HashSet::new();
+    for (index, (song, url)) in manifest.songs.iter().zip(urls).enumerate() {
+        if Url::parse(url).is_ok() {
+            continue;
+        }
+        let path = decode_path(url)?;
+        let title = (!manifest.title.is_empty()).then_some(manifest.title.as_str());
+        let name = song.album.as_deref().or(title).unwrap_or("Tracks");
+        match albums.iter_mut().find(|album| album.name == name) {
+            Some(album) => album.songs.push((index, path)),
+            None => {
+                let slug = slugify(name);
+                let slug = if slug.is_empty() { "tracks" } else { &slug };
+                let mut file = format!("{slug}.zip");
+                let mut n = 1;
+                while !files.insert(file.clone()) {
+                    n += 1;
+                    file = format!("{slug}-{n}.zip");
+                }
+                albums.push(Album {
+                    name: name.to_string(),
+                    file,
+                    songs: vec![(index, path)],
+                });
+            }
+        }
+    }
+    Ok(albums)
+}
+
+impl Generator for Archives {
+    fn name(&self) -> &str {
+        "zip"
+    }
+
+    fn generate(&self, manifest: &Manifest, urls: &[String]) -> anyhow::Result<Vec<Artifact>> {
+        let names = manifest.display_names();
+        let mut artifacts = Vec::new();
+        let mut checksums = String::new();
+        for album in albums(manifest, urls)? {
+            let mut entries = Vec::new();
+            let mut seen = HashSet::new();
+            for (index, path) in album.songs {
+                let file = self.base.join(&path);
+                let hash = sha256_file(&file)?;
+                let _ = writeln!(checksums, "{hash}  {}", path.display());
+                let extension = path
+                    .extension()
+                    .map(|extension| format!(".{}", extension.to_string_lossy()))
+                    .unwrap_or_default();
+                let name = names[index].replace('/', "-");
+                let mut entry = format!("{name}{extension}");
+                let mut n = 1;
+                while !seen.insert(entry.clone()) {
+                    n += 1;
+                    entry = format!("{name}-{n}{extension}");
+                }
+                entries.push((entry, file));
+            }
+            artifacts.push(Artifact {
+                path: album.file.into(),
+                contents: Contents::Stream(Box::new(move |writer| archive(writer, &entries))),
+            });
+        }
+        artifacts.push(Artifact {
+            path: CHECKSUMS.into(),
+            contents: checksums.into(),
+        });
+        Ok(artifacts)
+    }
+}
+
+/// Write a ZIP of the `files` to `writer`, each under the name it is paired with
+///
+/// The files are copied one by one, so they are never all held in memory.
+fn archive(writer: &mut dyn Sink, files: &[(String, PathBuf)]) -> anyhow::Result<()> {
+    let mut zip = ZipWriter::new(writer);
+    // Audio is compressed already
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    for (entry, file) in files {
+        let mut source =
+            File::open(file).with_context(|| format!("Failed to read {}", file.display()))?;
+        zip.start_file(entry, options)?;
+        io::copy(&mut source, &mut zip)
+            .with_context(|| format!("Failed to archive {}", file.display()))?;
+    }
+    zip.finish()?;
+    Ok(())
+}
@@ -0,0 +1,642 @@
+use anyhow::Context;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use laby::{html, iter, raw, render};
+use pulldown_cmark::{CowStr, Event, Tag};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use super::{archive, i18n::Labels, pretty::pretty, Artifact, Generator};
+use crate::{
+    lrc,
+    manifest::{Manifest, Song, Text},
+};
+
+/// Highlights the current line of time-synced lyrics as the track plays
+///
+/// Without it, the lyrics are shown as plain text.
+const LYRICS_SCRIPT: &str = r#"
+document.querySelectorAll(".lyrics").forEach(function (lyrics) {
+  lyrics.classList.add("synced");
+  var audio = lyrics.parentElement.querySelector("audio");
+  var lines = Array.prototype.slice.call(lyrics.querySelectorAll("[data-time]"));
+  audio.addEventListener("timeupdate", function () {
+    var current = null;
+    lines.forEach(function (line) {
+      if (parseFloat(line.dataset.time) <= audio.currentTime) current = line;
+    });
+    lines.forEach(function (line) {
+      line.classList.toggle("current", line === current);
+    });
+    if (current) lyrics.scrollTop = current.offsetTop - lyrics.offsetTop - lyrics.clientHeight / 2;
+  });
+});
+"#;
+
+/// Plays the tracks one after another, keeping the current one in the URL hash and history
+const PLAYER_SCRIPT: &str = r##"
+var songs = Array.prototype.slice.call(document.querySelectorAll(".song"));
+function audioOf(song) {
+  return song.querySelector("audio");
+}
+songs.forEach(function (song, index) {
+  var audio = audioOf(song);
+  audio.addEventListener("play", function () {
+    songs.forEach(function (other) {
+      if (other !== song) audioOf(other).pause();
+    });
+    if (location.hash !== "#" + song.id) history.pushState(null, "", "#" + song.id);
+  });
+  audio.addEventListener("ended", function () {
+    var next = songs[index + 1];
+    if (next) audioOf(next).play();
+  });
+});
+function resume() {
+  var song = document.getElementById(decodeURIComponent(location.hash.slice(1)));
+  if (songs.indexOf(song) < 0) return;
+  song.scrollIntoView();
+  var audio = audioOf(song);
+  if (audio.paused) {
+    var playing = audio.play();
+    // Browsers may refuse to play before any user interaction
+    if (playing) playing.catch(function () {});
+  }
+}
+window.addEventListener("popstate", resume);
+resume();
+"##;
+
+/// Posts a beacon to `BEACON` once per listen of a track, preceded by the definitions of
+/// `BEACON` and `TRACKS`, the name and ID of each track in order
+const ANALYTICS_SCRIPT: &str = r#"
+document.querySelectorAll(".song audio").forEach(function (audio, index) {
+  var track = TRACKS[index];
+  var counted = false;
+  audio.addEventListener("play", function () {
+    if (counted) return;
+    counted = true;
+    var url = BEACON.replace(/\{name\}/g, encodeURIComponent(track.name))
+      .replace(/\{id\}/g, encodeURIComponent(track.id));
+    var body = JSON.stringify(track);
+    if (!(navigator.sendBeacon && navigator.sendBeacon(url, body))) {
+      fetch(url, { method: "POST", body: body, keepalive: true, mode: "no-cors" });
+    }
+  });
+  audio.addEventListener("ended", function () {
+    counted = false;
+  });
+});
+"#;
+
+/// The colors of the themes, selected by the body class
+const THEME_STYLE: &str = ":root { --accent: #4a90d9; } \
+    body.dark { --background: #121212; --foreground: #e8e8e8; --muted: #9a9a9a; } \
+    body.light { --background: #fafafa; --foreground: #1a1a1a; --muted: #666666; } \
+    body.auto { --background: #121212; --foreground: #e8e8e8; --muted: #9a9a9a; } \
+    @media (prefers-color-scheme: light) { \
+    body.auto { --background: #fafafa; --foreground: #1a1a1a; --muted: #666666; } } \
+    body { background: var(--background); color: var(--foreground); \
+    color-scheme: light dark; font-family: sans-serif; } \
+    body.dark { color-scheme: dark; } body.light { color-scheme: light; } \
+    .song { border-left: 4px solid var(--accent); padding-left: 0.8em; margin: 1em 0; } \
+    .song audio { accent-color: var(--accent); } \
+    .artist, .details, .runtime { color: var(--muted); } \
+    .download { display: block; margin-top: 0.3em; } \
+    .badge { background: var(--accent); color: var(--background); border-radius: 0.3em; \
+    padding: 0 0.3em; margin-left: 0.4em; font-size: 0.6em; vertical-align: middle; } \
+    a { color: var(--accent); }";
+
+const LYRICS_STYLE: &str = ".lyrics { max-height: 12em; overflow-y: auto; } \
+    .lyrics p { margin: 0.2em 0; } \
+    .lyrics.synced p { opacity: 0.6; } \
+    .lyrics.synced p.current { font-weight: bold; opacity: 1; }";
+
+/// The largest images embedded into pages, in bytes
+const MAX_EMBEDDED_IMAGE: u64 = 256 * 1024;
+
+/// The HTML page with a player per track
+#[derive(Debug, Clone)]
+pub struct Html {
+    path: PathBuf,
+    base: PathBuf,
+    archives: bool,
+    script: Option<String>,
+    no_js: bool,
+    recent: Option<DateTime<Utc>>,
+    download_links: bool,
+    embed_images: bool,
+    pretty: bool,
+}
+
+impl Html {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            base: PathBuf::new(),
+            archives: false,
+            script: None,
+            no_js: false,
+            recent: None,
+            download_links: false,
+            embed_images: false,
+            pretty: false,
+        }
+    }
+
+    /// Link the album archives and checksums of the [`Archives`](super::Archives) output
+    pub fn archives(mut self, archives: bool) -> Self {
+        self.archives = archives;
+        self
+    }
+
+    /// Append `script` to the page, like the live reload of `Serve`
+    pub fn script(mut self, script: impl Into<String>) -> Self {
+        self.script = Some(script.into());
+        self
+    }
+
+    /// Leave out all scripts, for e-readers and other restrictive browsers
+    ///
+    /// The page works the same apart from the player enhancements: tracks do not play one
+    /// after another, and lyrics are not highlighted while playing.
+    pub fn no_js(mut self, no_js: bool) -> Self {
+        self.no_js = no_js;
+        self
+    }
+
+    /// Write the page to `path` instead
+    pub(super) fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// Link the file of each song for download next to its player, with its size if known,
+    /// except for songs marked `no_download`
+    pub fn download_links(mut self, download_links: bool) -> Self {
+        self.download_links = download_links;
+        self
+    }
+
+    /// Badge the songs added since `since` as new
+    pub fn highlight_since(mut self, since: DateTime<Utc>) -> Self {
+        self.recent = Some(since);
+        self
+    }
+
+    /// Embed the local images of descriptions and lyrics up to 256 KiB into the page, so it
+    /// works as a single file
+    ///
+    /// Like other files, images are resolved relative to the [`base`](Self::base).
+    pub fn embed_images(mut self, embed_images: bool) -> Self {
+        self.embed_images = embed_images;
+        self
+    }
+
+    /// Put each block element of the page on a line of its own, indented by its nesting, so
+    /// diffs of a committed page show the changed songs
+    pub fn pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Resolve files referenced by the manifest, like lyrics, relative to `base`
+    pub fn base(mut self, base: impl Into<PathBuf>) -> Self {
+        self.base = base.into();
+        self
+    }
+}
+
+impl Default for Html {
+    fn default() -> Self {
+        Self::new("index.html")
+    }
+}
+
+impl Generator for Html {
+    fn name(&self) -> &str {
+        "html"
+    }
+
+    fn generate(&self, manifest: &Manifest, urls: &[String]) -> anyhow::Result<Vec<Artifact>> {
+        let lyrics = manifest
+            .songs
+            .iter()
+            .map(|song| {
+                let Some(path) = &song.lyrics_lrc else {
+                    return Ok(None);
+                };
+                let path = self.base.join(path);
+                let lrc = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read lyrics {}", path.display()))?;
+                Ok(Some(lrc::parse(&lrc)))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let notes = manifest
+            .songs
+            .iter()
+            .map(|song| {
+                let html = |text: &Option<Text>| -> anyhow::Result<Option<String>> {
+                    let Some(text) = text else {
+                        return Ok(None);
+                    };
+                    let markdown = text
+                        .markdown(&self.base)
+                        .with_context(|| format!("Failed to read the texts of {}", song.name))?;
+                    let base = self.embed_images.then_some(self.base.as_path());
+                    Ok(Some(markdown_html(&markdown, base)))
+                };
+                Ok((html(&song.description)?, html(&song.lyrics)?))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let stylesheet = manifest
+            .stylesheet
+            .as_ref()
+            .map(|path| {
+                let path = self.base.join(path);
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read stylesheet {}", path.display()))
+            })
+            .transpose()?;
+        let albums = if self.archives {
+            archive::albums(manifest, urls)?
+        } else {
+            Vec::new()
+        };
+        let mut page = self.render(manifest, urls, lyrics, notes, albums, stylesheet);
+        if self.pretty {
+            page = pretty(&page);
+        }
+        Ok(vec![Artifact {
+            path: self.path.clone(),
+            contents: page.into(),
+        }])
+    }
+}
+
+impl Html {
+    /// Render the page of `manifest`, with `urls` as the sources, `lyrics` as the time-synced
+    /// lyrics and `notes` as the HTML description and lyrics of its songs in order, linking the
+    /// archives of `albums`, with the custom `stylesheet`
+    fn render(
+        &self,
+        manifest: &Manifest,
+        urls: &[String],
+        lyrics: Vec<Option<Vec<lrc::Line>>>,
+        notes: Vec<(Option<String>, Option<String>)>,
+        albums: Vec<archive::Album>,
+        stylesheet: Option<String>,
+    ) -> String {
+        let no_js = self.no_js;
+        let labels = Labels::new(manifest);
+        let theme = manifest.theme.unwrap_or_default();
+        let accent = manifest
+            .accent
+            .as_deref()
+            .filter(|accent| is_css_color(accent))
+            .map(|accent| laby::style!(format!(":root {{ --accent: {accent}; }}")));
+
+        let synced = lyrics.iter().any(Option::is_some);
+        let style = synced.then(|| laby::style!(raw!(LYRICS_STYLE)));
+        let script = (synced && !no_js).then(|| laby::script!(raw!(LYRICS_SCRIPT)));
+        let player = (!no_js).then(|| laby::script!(raw!(PLAYER_SCRIPT)));
+        let analytics = manifest
+            .analytics
+            .as_ref()
+            .filter(|_| !no_js)
+            .map(|analytics| laby::script!(raw!(analytics_script(manifest, &analytics.beacon))));
+        let extra = self.script.clone().filter(|_| !no_js);
+
+        let total: f64 = manifest.songs.iter().filter_map(|s| s.duration).sum();
+        let runtime = (total > 0.0).then(|| {
+            laby::p!(
+                class = "runtime",
+                labels.format("total_runtime", format_duration(total))
+            )
+        });
+
+        let artist = (!manifest.various_artists)
+            .then_some(manifest.artist.clone())
+            .flatten()
+            .map(|artist| laby::p!(class = "artist", artist));
+
+        let names = manifest.display_names().into_iter().zip(manifest.slugs());
+        let songs = manifest
+            .songs
+            .iter()
+            .zip(names)
+            .zip(urls)
+            .zip(lyrics)
+            .zip(notes);
+        let sections = section_headings(manifest);
+        let audio_tags = iter!(songs.zip(sections).map(
+            |(((((s, (name, slug)), src), lyrics), notes), section)| {
+                let source = source(manifest, s, src.clone());
+                let recent = self
+                    .recent
+                    .is_some_and(|since| s.added.is_some_and(|added| added >= since));
+                let artist = manifest
+                    .various_artists
+                    .then(|| manifest.artist_of(s))
+                    .flatten()
+                    .map(|artist| laby::p!(class = "artist", artist.to_string()));
+                let details = details(s).map(|details| laby::span!(class = "details", details));
+                let download = (self.download_links && !s.no_download).then(|| {
+                    let label = match s.size {
+                        Some(size) => {
+                            format!("{} ({})", labels.get("download"), format_size(size))
+                        }
+                        None => labels.get("download").to_string(),
+                    };
+                    laby::a!(class = "download", href = src.clone(), download = "", label)
+                });
+                // Browsers offer saving from the player menu unless asked not to
+                let audio = laby::frag_match!(match s.no_download {
+                    true => laby::audio!(
+                        class = "track",
+                        controls = "controls",
+                        controlslist = "nodownload",
+                        source
+                    ),
+                    false => laby::audio!(class = "track", controls = "controls", source),
+                });
+                let lyrics = lyrics.map(|lines| {
+                    laby::div!(
+                        class = "lyrics",
+                        iter!(lines.into_iter().map(|line| laby::p!(
+                            "data-time" = format!("{:.2}", line.time),
+                            line.text
+                        )))
+                    )
+                });
+                let (description, text) = notes;
+                let collapsible = |class: &'static str, key: &str, html: Option<String>| {
+                    let summary = labels.get(key).to_string();
+                    html.map(|html| {
+                        laby::details!(
+                            class = class,
+                            laby::summary!(summary),
+                            laby::div!(laby::raw!(html))
+                        )
+                    })
+                };
+                let description = collapsible("description", "description", description);
+                let text = collapsible("lyrics-text", "lyrics", text);
+                laby::frag!(
+                    section.map(|album| laby::h2!(class = "album", album)),
+                    laby::div!(
+                        class = if recent { "song recent" } else { "song" },
+                        id = slug,
+                        laby::h3!(
+                            name,
+                            recent.then(|| laby::span!(
+                                class = "badge",
+                                labels.get("new").to_string()
+                            ))
+                        ),
+                        artist,
+                        audio,
+                        details,
+                        download,
+                        description,
+                        text,
+                        lyrics
+                    )
+                )
+            }
+        ));
+
+        let downloads = (!albums.is_empty()).then(|| {
+            laby::div!(
+                class = "downloads",
+                iter!(albums.into_iter().map(|album| {
+                    laby::a!(
+                        href = album.file,
+                        download = "",
+                        labels.format("download_album", &album.name)
+                    )
+                })),
+                laby::a!(href = archive::CHECKSUMS, archive::CHECKSUMS)
+            )
+        });
+
+        let n = html!(
+            lang = manifest.lang.clone().unwrap_or_else(|| "en".to_string()),
+            head!(
+                meta!(charset = "utf-8"),
+                meta!(
+                    name = "viewport",
+                    content = "width=device-width, initial-scale=1"
+                ),
+                title!(manifest.title.clone()),
+                meta_tags(manifest),
+                style!(raw!(THEME_STYLE)),
+                accent,
+                style,
+                stylesheet.map(|stylesheet| laby::style!(raw!(stylesheet)))
+            ),
+            body!(
+                class = theme.class(),
+                artist,
+                runtime,
+                audio_tags,
+                downloads,
+                player,
+                analytics,
+                script,
+                extra.map(|extra| laby::script!(raw!(extra)))
+            ),
+        );
+
+        render!(n)
+    }
+}
+
+/// The analytics script of `manifest`, posting beacons to `beacon`
+fn analytics_script(manifest: &Manifest, beacon: &str) -> String {
+    let tracks = manifest
+        .songs
+        .iter()
+        .zip(manifest.slugs())
+        .map(|(song, id)| serde_json::json!({ "name": song.name, "id": id }))
+        .collect::<Vec<_>>();
+    // `</` would end the script element early
+    let json = |value: serde_json::Value| value.to_string().replace("</", "<\\/");
+    format!(
+        "var BEACON = {};\nvar TRACKS = {};{ANALYTICS_SCRIPT}",
+        json(beacon.into()),
+        json(tracks.into())
+    )
+}
+
+/// The HTML of the Markdown `markdown`, embedding its small local images below `embed`
+fn markdown_html(markdown: &str, embed: Option<&Path>) -> String {
+    let events = pulldown_cmark::Parser::new(markdown).map(|event| match (event, embed) {
+        (Event::Start(Tag::Image(kind, src, title)), Some(base)) => {
+            let src = match data_url(base, &src) {
+                Some(data) => CowStr::from(data),
+                None => src,
+            };
+            Event::Start(Tag::Image(kind, src, title))
+        }
+        (event, _) => event,
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+    html
+}
+
+/// The `data:` URL of the local image `src` below `base`, if it is small enough
+fn data_url(base: &Path, src: &str) -> Option<String> {
+    if Url::parse(src).is_ok() || src.starts_with('/') {
+        return None;
+    }
+    let path = base.join(src);
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let mime = match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        _ => return None,
+    };
+    let embeddable = std::fs::metadata(&path).map(|metadata| metadata.len() <= MAX_EMBEDDED_IMAGE);
+    match embeddable.and_then(|small| small.then(|| std::fs::read(&path)).transpose()) {
+        Ok(Some(image)) => {
+            let data = base64::engine::general_purpose::STANDARD.encode(image);
+            Some(format!("data:{mime};base64,{data}"))
+        }
+        Ok(None) => {
+            tracing::warn!(
+                "Not embedding {}, it is larger than 256 KiB",
+                path.display()
+            );
+            None
+        }
+        Err(e) => {
+            tracing::warn!("Not embedding {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// The `source` element of `song` at `src`, with its MIME type if known
+fn source(manifest: &Manifest, song: &Song, src: String) -> impl laby::Render {
+    laby::frag_match!(match manifest.mime_of(song) {
+        Some(mime) => laby::source!(src = src, "type" = mime.to_string()),
+        None => {
+            tracing::warn!(
+                "No MIME type known for {}, set its mime or add its extension to mime_types",
+                song.path.display()
+            );
+            laby::source!(src = src)
+        }
+    })
+}
+
+/// The album heading to put before each song of `manifest`, where its album starts
+///
+/// Pages of a single album, like most, get no headings.
+fn section_headings(manifest: &Manifest) -> Vec<Option<String>> {
+    let albums = manifest
+        .songs
+        .iter()
+        .filter_map(|song| song.album.as_deref())
+        .collect::<std::collections::HashSet<_>>();
+    let mut previous = None;
+    manifest
+        .songs
+        .iter()
+        .map(|song| {
+            let album = song.album.as_deref();
+            let starts = albums.len() > 1 && album.is_some() && album != previous;
+            previous = album;
+            starts.then(|| album.map(str::to_string)).flatten()
+        })
+        .collect()
+}
+
+/// Whether `accent` is safe to use as CSS color, like `#e0457b` or `rgb(224, 69, 123)`
+fn is_css_color(accent: &str) -> bool {
+    !accent.is_empty()
+        && accent
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "#(),.% ".contains(c))
+}
+
+/// The description, OpenGraph and Twitter card tags of `manifest`
+fn meta_tags(manifest: &Manifest) -> impl laby::Render {
+    let description = manifest.description.clone();
+    let card = match manifest.image {
+        Some(_) => "summary_large_image",
+        None => "summary",
+    };
+    laby::frag!(
+        description
+            .clone()
+            .map(|description| laby::meta!(name = "description", content = description)),
+        laby::meta!(property = "og:title", content = manifest.title.clone()),
+        laby::meta!(property = "og:type", content = "music.playlist"),
+        description
+            .clone()
+            .map(|description| laby::meta!(property = "og:description", content = description)),
+        manifest
+            .url
+            .clone()
+            .map(|url| laby::meta!(property = "og:url", content = url)),
+        manifest
+            .image
+            .clone()
+            .map(|image| laby::meta!(property = "og:image", content = image)),
+        laby::meta!(name = "twitter:card", content = card),
+        laby::meta!(name = "twitter:title", content = manifest.title.clone()),
+        description.map(|description| {
+            laby::meta!(name = "twitter:description", content = description)
+        }),
+        manifest
+            .image
+            .clone()
+            .map(|image| laby::meta!(name = "twitter:image", content = image)),
+    )
+}
+
+/// The year, duration and size of `song` as far as known, like `1998 · 3:07 · 4.5 MB`
+fn details(song: &Song) -> Option<String> {
+    let parts = [
+        song.year.map(|year| year.to_string()),
+        song.duration.map(format_duration),
+        song.size.map(format_size),
+    ];
+    let parts = parts.into_iter().flatten().collect::<Vec<_>>();
+    (!parts.is_empty()).then(|| parts.join(" · "))
+}
+
+/// Format `seconds` as `m:ss`, or `h:mm:ss` from an hour on
+pub(crate) fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+/// Format `bytes` with a decimal unit, like `4.5 MB`
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["kB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1000.0 {
+            break;
+        }
+        size /= 1000.0;
+        unit = next;
+    }
+    format!("{size:.1} {unit}")
+}
@@ -0,0 +1,104 @@
+//! Translations of the text of generated pages
+
+use std::fmt::Display;
+
+use crate::manifest::Manifest;
+
+/// The built-in translations by language, the first being the fallback
+///
+/// `{}` is replaced by the runtime, album name, or season number.
+const TRANSLATIONS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "en",
+        &[
+            ("total_runtime", "Total runtime {}"),
+            ("download_album", "Download {} (ZIP)"),
+            ("new", "New"),
+            ("season", "Season {}"),
+            ("download", "Download"),
+            ("description", "Notes"),
+            ("lyrics", "Lyrics"),
+        ],
+    ),
+    (
+        "de",
+        &[
+            ("total_runtime", "Gesamtlaufzeit {}"),
+            ("download_album", "{} herunterladen (ZIP)"),
+            ("new", "Neu"),
+            ("season", "Staffel {}"),
+            ("download", "Herunterladen"),
+            ("description", "Anmerkungen"),
+            ("lyrics", "Songtext"),
+        ],
+    ),
+    (
+        "fr",
+        &[
+            ("total_runtime", "Durée totale {}"),
+            ("download_album", "Télécharger {} (ZIP)"),
+            ("new", "Nouveau"),
+            ("season", "Saison {}"),
+            ("download", "Télécharger"),
+            ("description", "Notes"),
+            ("lyrics", "Paroles"),
+        ],
+    ),
+    (
+        "es",
+        &[
+            ("total_runtime", "Duración total {}"),
+            ("download_album", "Descargar {} (ZIP)"),
+            ("new", "Nuevo"),
+            ("season", "Temporada {}"),
+            ("download", "Descargar"),
+            ("description", "Notas"),
+            ("lyrics", "Letra"),
+        ],
+    ),
+];
+
+/// The texts of the pages of a manifest in its `lang`, overridden by its `strings`
+pub(crate) struct Labels<'a> {
+    manifest: &'a Manifest,
+    builtin: &'static [(&'static str, &'static str)],
+}
+
+impl<'a> Labels<'a> {
+    pub(crate) fn new(manifest: &'a Manifest) -> Self {
+        // Regional variants like `de-AT` fall back to the language
+        let language = manifest
+            .lang
+            .as_deref()
+            .and_then(|lang| lang.split(['-', '_']).next())
+            .map(str::to_ascii_lowercase);
+        let builtin = TRANSLATIONS
+            .iter()
+            .find(|(lang, _)| Some(*lang) == language.as_deref())
+            .unwrap_or(&TRANSLATIONS[0])
+            .1;
+        Self { manifest, builtin }
+    }
+
+    /// The text `key`, like `new`
+    pub(crate) fn get<'k>(&'k self, key: &'k str) -> &'k str {
+        let lookup = |table: &'static [(&'static str, &'static str)]| {
+            table
+                .iter()
+                .find(|(other, _)| *other == key)
+                .map(|(_, text)| *text)
+        };
+        self.manifest
+            .strings
+            .get(key)
+            .map(String::as_str)
+            .or_else(|| lookup(self.builtin))
+            .or_else(|| lookup(TRANSLATIONS[0].1))
+            .unwrap_or(key)
+    }
+
+    /// The text `key` with `value` in place of `{}`, like `Season 2`
+    pub(crate) fn format(&self, key: &str, value: impl Display) -> String {
+        self.get(key).replacen("{}", &value.to_string(), 1)
+    }
+}
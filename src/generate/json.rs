@@ -0,0 +1,109 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::PathBuf;
+
+use super::{Artifact, Generator};
+use crate::manifest::Manifest;
+
+/// A machine-readable index of the tracks, for JavaScript frontends
+#[derive(Debug, Clone)]
+pub struct Json {
+    path: PathBuf,
+}
+
+impl Json {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Default for Json {
+    fn default() -> Self {
+        Self::new("tracks-index.json")
+    }
+}
+
+#[derive(Serialize)]
+struct Index<'a> {
+    title: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    album_artist: Option<&'a str>,
+    tracks: Vec<Track<'a>>,
+}
+
+#[derive(Serialize)]
+struct Track<'a> {
+    id: String,
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    number: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    album: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    artist: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    year: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    series: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    season: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    episode: Option<u32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+    url: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    added: Option<DateTime<Utc>>,
+}
+
+impl Generator for Json {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn generate(&self, manifest: &Manifest, urls: &[String]) -> anyhow::Result<Vec<Artifact>> {
+        let numbers = manifest.track_numbers();
+        let tracks = manifest
+            .songs
+            .iter()
+            .zip(urls)
+            .zip(manifest.slugs())
+            .enumerate()
+            .map(|(index, ((song, url), id))| Track {
+                id,
+                name: &song.name,
+                number: numbers.as_ref().map(|numbers| numbers[index]),
+                album: song.album.as_deref(),
+                artist: manifest.artist_of(song),
+                year: song.year,
+                series: song.series.as_deref(),
+                season: song.season,
+                episode: song.episode,
+                tags: &song.tags,
+                url,
+                mime: manifest.mime_of(song),
+                duration: song.duration,
+                size: song.size,
+                added: song.added,
+            })
+            .collect();
+        let index = Index {
+            title: &manifest.title,
+            album_artist: manifest.artist.as_deref(),
+            tracks,
+        };
+        let contents =
+            serde_json::to_vec_pretty(&index).context("Failed to serialize track index")?;
+        Ok(vec![Artifact {
+            path: self.path.clone(),
+            contents: contents.into(),
+        }])
+    }
+}
@@ -0,0 +1,71 @@
+use std::{fmt::Write, path::PathBuf};
+
+use super::{format_duration, Artifact, Generator};
+use crate::manifest::Manifest;
+
+/// A Markdown track listing, like for a README or a wiki page
+#[derive(Debug, Clone)]
+pub struct Markdown {
+    path: PathBuf,
+}
+
+impl Markdown {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Default for Markdown {
+    fn default() -> Self {
+        Self::new("tracks.md")
+    }
+}
+
+impl Generator for Markdown {
+    fn name(&self) -> &str {
+        "md"
+    }
+
+    fn generate(&self, manifest: &Manifest, urls: &[String]) -> anyhow::Result<Vec<Artifact>> {
+        let numbers = manifest.track_numbers();
+        let mut markdown = format!("# {}\n\n", escape(&manifest.title));
+        if let Some(artist) = &manifest.artist {
+            writeln!(markdown, "By {}\n", escape(artist))?;
+        }
+        markdown.push_str("| # | Title | Artist | Album | Duration |\n");
+        markdown.push_str("|--:|-------|--------|-------|---------:|\n");
+        for (index, (song, url)) in manifest.songs.iter().zip(urls).enumerate() {
+            let number = numbers.as_ref().map_or(index + 1, |numbers| numbers[index]);
+            let cell = |value: Option<&str>| value.map(escape).unwrap_or_default();
+            writeln!(
+                markdown,
+                "| {number} | [{}](<{}>) | {} | {} | {} |",
+                escape(&song.name),
+                url.replace('<', "%3C").replace('>', "%3E"),
+                cell(manifest.artist_of(song)),
+                cell(song.album.as_deref()),
+                song.duration.map(format_duration).unwrap_or_default(),
+            )?;
+        }
+        Ok(vec![Artifact {
+            path: self.path.clone(),
+            contents: markdown.into(),
+        }])
+    }
+}
+
+/// Escape `text` for use in Markdown table cells and link texts
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
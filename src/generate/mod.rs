@@ -0,0 +1,194 @@
+//! Output formats generated from a manifest
+//!
+//! Each format, or emitter, implements [`Generator`]. Downstream crates can implement it for
+//! their own outputs and [`register`](Generators::register) them next to the built-in ones.
+
+use anyhow::{bail, Context};
+use std::{
+    fmt,
+    fs::File,
+    io::{Cursor, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{atomic, manifest::Manifest};
+
+mod archive;
+mod html;
+mod i18n;
+mod json;
+mod markdown;
+mod pretty;
+mod rss;
+mod season;
+mod site;
+mod sitemap;
+
+pub use archive::{Archives, CHECKSUMS};
+pub use html::Html;
+pub(crate) use html::{format_duration, format_size};
+pub use json::Json;
+pub use markdown::Markdown;
+pub use rss::Rss;
+pub use season::Seasons;
+pub use site::{Site, SiteKind};
+pub use sitemap::Sitemap;
+
+/// A generated file
+#[derive(Debug)]
+pub struct Artifact {
+    /// The path of the file, relative to the output directory
+    pub path: PathBuf,
+    /// The contents of the file
+    pub contents: Contents,
+}
+
+/// The contents of a generated file
+pub enum Contents {
+    /// Generated in memory
+    Bytes(Vec<u8>),
+    /// Generated while writing the file, for contents too large to hold in memory
+    Stream(Box<Stream>),
+}
+
+/// Writes streamed contents to the sink
+pub type Stream = dyn FnOnce(&mut dyn Sink) -> anyhow::Result<()>;
+
+/// Where streamed contents are written to, seekable for formats like ZIP
+pub trait Sink: Write + Seek {}
+
+impl<T: Write + Seek> Sink for T {}
+
+impl Contents {
+    /// The contents in memory, generating them if streamed
+    pub fn into_bytes(self) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Contents::Bytes(bytes) => Ok(bytes),
+            Contents::Stream(write) => {
+                let mut bytes = Cursor::new(Vec::new());
+                write(&mut bytes)?;
+                Ok(bytes.into_inner())
+            }
+        }
+    }
+
+    /// Write the contents to `path` atomically
+    pub fn write(self, path: &Path) -> anyhow::Result<()> {
+        let result = match self {
+            Contents::Bytes(bytes) => atomic::write(path, bytes).map_err(Into::into),
+            Contents::Stream(write) => atomic::write_with(path, |file: &mut File| write(file)),
+        };
+        result.with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+impl From<Vec<u8>> for Contents {
+    fn from(bytes: Vec<u8>) -> Self {
+        Contents::Bytes(bytes)
+    }
+}
+
+impl From<String> for Contents {
+    fn from(text: String) -> Self {
+        Contents::Bytes(text.into_bytes())
+    }
+}
+
+impl fmt::Debug for Contents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Contents::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            Contents::Stream(_) => f.write_str("Stream"),
+        }
+    }
+}
+
+/// Turns a manifest into artifacts
+pub trait Generator {
+    /// The name this output format is selected by, like `html`
+    fn name(&self) -> &str;
+
+    /// Generate the artifacts of `manifest`
+    ///
+    /// `urls` are the resolved URLs of the manifest songs, in order.
+    fn generate(&self, manifest: &Manifest, urls: &[String]) -> anyhow::Result<Vec<Artifact>>;
+}
+
+/// A set of generators, selectable by name
+#[derive(Default)]
+pub struct Generators {
+    generators: Vec<Box<dyn Generator>>,
+}
+
+impl Generators {
+    /// The built-in generators, writing the HTML page to `page`
+    ///
+    /// Files referenced by the manifest are resolved relative to `base`.
+    pub fn builtin(page: impl Into<PathBuf>, base: impl Into<PathBuf>) -> Self {
+        let base = base.into();
+        let mut generators = Self::default();
+        generators.register(Html::new(page).base(&base));
+        generators.register(Archives::new(&base));
+        generators.register(Seasons::new(base));
+        generators.register(Rss::default());
+        generators.register(Json::default());
+        generators.register(Markdown::default());
+        generators.register(Sitemap::default());
+        generators
+    }
+
+    /// Add `generator`, replacing any registered generator of the same name
+    pub fn register(&mut self, generator: impl Generator + 'static) {
+        self.generators.retain(|g| g.name() != generator.name());
+        self.generators.push(Box::new(generator));
+    }
+
+    /// The generator registered as `name`
+    pub fn get(&self, name: &str) -> Option<&dyn Generator> {
+        self.generators
+            .iter()
+            .find(|g| g.name() == name)
+            .map(|g| g.as_ref())
+    }
+
+    /// The names of all registered generators
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.generators.iter().map(|g| g.name())
+    }
+
+    /// Run the generators selected by `names`, in order
+    pub fn generate(
+        &self,
+        names: &[String],
+        manifest: &Manifest,
+        urls: &[String],
+    ) -> anyhow::Result<Vec<Artifact>> {
+        let mut artifacts = Vec::new();
+        for name in names {
+            let Some(generator) = self.get(name) else {
+                let known = self.names().collect::<Vec<_>>().join(", ");
+                bail!("Unknown output format {name}, expected one of {known}");
+            };
+            let start = std::time::Instant::now();
+            artifacts.extend(generator.generate(manifest, urls)?);
+            tracing::debug!(format = name, elapsed = ?start.elapsed(), "Generated");
+        }
+        Ok(artifacts)
+    }
+}
+
+/// Escape `text` for use in XML and HTML text and attribute values
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
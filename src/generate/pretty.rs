@@ -0,0 +1,333 @@
+//! Indenting rendered HTML, to keep diffs of committed pages reviewable
+
+/// Elements laid out as blocks, which are put on lines of their own
+const BLOCKS: &[&str] = &[
+    "article",
+    "aside",
+    "audio",
+    "blockquote",
+    "body",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "li",
+    "link",
+    "main",
+    "meta",
+    "nav",
+    "ol",
+    "p",
+    "section",
+    "source",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+    "video",
+];
+
+/// Elements without closing tag
+const VOIDS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements whose contents are kept verbatim, as whitespace or text in them is significant
+const VERBATIM: &[&str] = &["pre", "script", "style", "textarea"];
+
+#[derive(Debug)]
+enum Node<'a> {
+    Text(&'a str),
+    /// A whole element kept as it is, or a comment or doctype
+    Verbatim(&'a str),
+    Element {
+        name: String,
+        open: &'a str,
+        children: Vec<Node<'a>>,
+        close: Option<&'a str>,
+    },
+}
+
+impl Node<'_> {
+    fn is_block(&self) -> bool {
+        match self {
+            Node::Text(_) => false,
+            Node::Verbatim(text) => !text.starts_with("<textarea"),
+            Node::Element { name, .. } => BLOCKS.contains(&name.as_str()),
+        }
+    }
+
+    /// Append this node as it was rendered
+    fn write_inline(&self, out: &mut String) {
+        match self {
+            Node::Text(text) | Node::Verbatim(text) => out.push_str(text),
+            Node::Element {
+                open,
+                children,
+                close,
+                ..
+            } => {
+                out.push_str(open);
+                for child in children {
+                    child.write_inline(out);
+                }
+                out.push_str(close.unwrap_or_default());
+            }
+        }
+    }
+
+    /// Append this node on lines of its own, indented by `depth`
+    fn write_block(&self, depth: usize, out: &mut String) {
+        let indent = "  ".repeat(depth);
+        match self {
+            Node::Element {
+                open,
+                children,
+                close,
+                ..
+            } if children.iter().any(Node::is_block) => {
+                out.push_str(&indent);
+                out.push_str(open);
+                out.push('\n');
+                write_children(children, depth + 1, out);
+                if let Some(close) = close {
+                    out.push_str(&indent);
+                    out.push_str(close);
+                    out.push('\n');
+                }
+            }
+            node => {
+                out.push_str(&indent);
+                node.write_inline(out);
+                out.push('\n');
+            }
+        }
+    }
+}
+
+/// Append `nodes` at `depth`, blocks on lines of their own and the inline runs between them
+/// on one line each
+fn write_children(nodes: &[Node], depth: usize, out: &mut String) {
+    let mut run = String::new();
+    let flush = |run: &mut String, out: &mut String| {
+        // Whitespace next to blocks is not rendered
+        let text = run.trim();
+        if !text.is_empty() {
+            out.push_str(&"  ".repeat(depth));
+            out.push_str(text);
+            out.push('\n');
+        }
+        run.clear();
+    };
+    for node in nodes {
+        if node.is_block() {
+            flush(&mut run, out);
+            node.write_block(depth, out);
+        } else {
+            node.write_inline(&mut run);
+        }
+    }
+    flush(&mut run, out);
+}
+
+/// The name of the element opened or closed by `tag`, in lowercase
+fn tag_name(tag: &str) -> String {
+    tag.trim_start_matches(['<', '/'])
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+/// The length of the tag at the start of `html`, skipping `>` in quoted attribute values
+fn tag_len(html: &str) -> usize {
+    let mut quote = None;
+    for (index, c) in html.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('>', None) => return index + 1,
+            _ => {}
+        }
+    }
+    html.len()
+}
+
+/// Parse `html` into nodes, closing unclosed elements where a parent closes
+fn parse(html: &str) -> Vec<Node<'_>> {
+    // The open elements with their opening tag and children so far
+    let mut stack: Vec<(String, &str, Vec<Node>)> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut rest = html;
+    fn close<'a>(
+        stack: &mut Vec<(String, &'a str, Vec<Node<'a>>)>,
+        nodes: &mut Vec<Node<'a>>,
+        tag: Option<&'a str>,
+    ) {
+        let (name, open, children) = stack.pop().expect("an open element");
+        let element = Node::Element {
+            name,
+            open,
+            children,
+            close: tag,
+        };
+        match stack.last_mut() {
+            Some((_, _, siblings)) => siblings.push(element),
+            None => nodes.push(element),
+        }
+    }
+    while !rest.is_empty() {
+        let (node, len) = if let Some(comment) = rest.strip_prefix("<!--") {
+            let len = comment.find("-->").map_or(rest.len(), |end| end + 7);
+            (Some(Node::Verbatim(&rest[..len])), len)
+        } else if rest.starts_with("</") {
+            let len = tag_len(rest);
+            let name = tag_name(&rest[..len]);
+            if let Some(position) = stack.iter().rposition(|(open, _, _)| *open == name) {
+                while stack.len() > position + 1 {
+                    close(&mut stack, &mut nodes, None);
+                }
+                close(&mut stack, &mut nodes, Some(&rest[..len]));
+                (None, len)
+            } else {
+                (Some(Node::Text(&rest[..len])), len)
+            }
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic())
+        {
+            let len = tag_len(rest);
+            let name = tag_name(&rest[..len]);
+            if VERBATIM.contains(&name.as_str()) {
+                let end = format!("</{name}");
+                let len = rest[len..]
+                    .to_ascii_lowercase()
+                    .find(&end)
+                    .map_or(rest.len(), |end| {
+                        let close = len + end;
+                        close + tag_len(&rest[close..])
+                    });
+                (Some(Node::Verbatim(&rest[..len])), len)
+            } else if VOIDS.contains(&name.as_str()) || rest[..len].ends_with("/>") {
+                let element = Node::Element {
+                    name,
+                    open: &rest[..len],
+                    children: Vec::new(),
+                    close: None,
+                };
+                (Some(element), len)
+            } else {
+                stack.push((name, &rest[..len], Vec::new()));
+                (None, len)
+            }
+        } else if rest.starts_with("<!") {
+            let len = tag_len(rest);
+            (Some(Node::Verbatim(&rest[..len])), len)
+        } else {
+            let first = rest.chars().next().map_or(1, char::len_utf8);
+            let len = rest[first..]
+                .find('<')
+                .map_or(rest.len(), |end| end + first);
+            (Some(Node::Text(&rest[..len])), len)
+        };
+        if let Some(node) = node {
+            match stack.last_mut() {
+                Some((_, _, children)) => children.push(node),
+                None => nodes.push(node),
+            }
+        }
+        rest = &rest[len..];
+    }
+    while !stack.is_empty() {
+        close(&mut stack, &mut nodes, None);
+    }
+    nodes
+}
+
+/// `html` with each block element on a line of its own, indented by its nesting
+///
+/// Scripts, styles and preformatted text are kept as they are, and so is the whitespace that
+/// browsers render, so the page looks the same.
+pub(crate) fn pretty(html: &str) -> String {
+    let mut out = String::with_capacity(html.len() * 2);
+    write_children(&parse(html), 0, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_indented() {
+        assert_eq!(
+            pretty("<ul><li>One <b>two</b></li><li>Three</li></ul>"),
+            "<ul>\n  <li>One <b>two</b></li>\n  <li>Three</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn void_elements_are_not_closed() {
+        assert_eq!(
+            pretty("<head><meta charset=\"utf-8\"><link rel=\"icon\" href=\"a>b.png\"></head>"),
+            "<head>\n  <meta charset=\"utf-8\">\n  <link rel=\"icon\" href=\"a>b.png\">\n</head>\n"
+        );
+        assert_eq!(
+            pretty("<p>One<br>two<img src=\"a.png\"/></p>"),
+            "<p>One<br>two<img src=\"a.png\"/></p>\n"
+        );
+    }
+
+    #[test]
+    fn verbatim_elements_are_kept() {
+        let html = "<div><pre>  a\n<b>b</b></pre><script>if (a<b) { x(\"</div>\") }</script></div>";
+        assert_eq!(
+            pretty(html),
+            "<div>\n  <pre>  a\n<b>b</b></pre>\n  \
+             <script>if (a<b) { x(\"</div>\") }</script>\n</div>\n"
+        );
+    }
+
+    #[test]
+    fn pretty_is_idempotent() {
+        let html = "<!DOCTYPE html><html><head><title>T</title><style>p { }</style></head>\
+                    <body><main><p>Text <a href=\"#\">link</a></p><!-- note --><hr></main>\
+                    </body></html>";
+        let once = pretty(html);
+        assert_eq!(pretty(&once), once);
+    }
+
+    #[test]
+    fn pretty_is_idempotent_for_markdown() {
+        let html = "<div class=\"description\">\n<h2>Notes</h2>\n<p>Recorded live,\n\
+                    <em>twice</em>.</p>\nLoose text\n<ul>\n<li>One</li>\n<li>Two</li>\n</ul>\n\
+                    </div>\n";
+        let once = pretty(html);
+        assert_eq!(
+            once,
+            "<div class=\"description\">\n  <h2>Notes</h2>\n  \
+             <p>Recorded live,\n<em>twice</em>.</p>\n  Loose text\n  <ul>\n    <li>One</li>\n    <li>Two</li>\n  </ul>\n</div>\n"
+        );
+        assert_eq!(pretty(&once), once);
+    }
+}
@@ -0,0 +1,82 @@
+use std::{fmt::Write, path::PathBuf};
+
+use super::{escape, Artifact, Generator};
+use crate::manifest::Manifest;
+
+/// An RSS 2.0 feed with one item per track, the audio attached as enclosure
+#[derive(Debug, Clone)]
+pub struct Rss {
+    path: PathBuf,
+}
+
+impl Rss {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Default for Rss {
+    fn default() -> Self {
+        Self::new("feed.xml")
+    }
+}
+
+impl Generator for Rss {
+    fn name(&self) -> &str {
+        "rss"
+    }
+
+    fn generate(&self, manifest: &Manifest, urls: &[String]) -> anyhow::Result<Vec<Artifact>> {
+        let title = escape(&manifest.title);
+        let mut feed = String::new();
+        feed.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        feed.push_str("<rss version=\"2.0\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\"");
+        let episodic = manifest
+            .songs
+            .iter()
+            .any(|song| song.season.is_some() || song.episode.is_some());
+        if episodic {
+            feed.push_str(" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\"");
+        }
+        feed.push_str(">\n<channel>\n");
+        let _ = writeln!(feed, "<title>{title}</title>");
+        let link = manifest.url.as_deref().unwrap_or(&manifest.prefix);
+        let _ = writeln!(feed, "<link>{}</link>", escape(link));
+        let _ = writeln!(feed, "<description>{title}</description>");
+        if let Some(lang) = &manifest.lang {
+            let _ = writeln!(feed, "<language>{}</language>", escape(lang));
+        }
+        if let Some(artist) = &manifest.artist {
+            let _ = writeln!(feed, "<dc:creator>{}</dc:creator>", escape(artist));
+        }
+        let names = manifest.display_names();
+        let songs = manifest.songs.iter().zip(manifest.slugs());
+        for (((song, id), name), url) in songs.zip(names).zip(urls) {
+            let url = escape(url);
+            feed.push_str("<item>\n");
+            let _ = writeln!(feed, "<title>{}</title>", escape(&name));
+            let _ = writeln!(feed, "<guid isPermaLink=\"false\">{id}</guid>");
+            if let Some(artist) = manifest.artist_of(song) {
+                let _ = writeln!(feed, "<dc:creator>{}</dc:creator>", escape(artist));
+            }
+            if let Some(season) = song.season {
+                let _ = writeln!(feed, "<itunes:season>{season}</itunes:season>");
+            }
+            if let Some(episode) = song.episode {
+                let _ = writeln!(feed, "<itunes:episode>{episode}</itunes:episode>");
+            }
+            let _ = writeln!(
+                feed,
+                "<enclosure url=\"{url}\" length=\"{}\" type=\"{}\"/>",
+                song.size.unwrap_or_default(),
+                escape(manifest.mime_of(song).unwrap_or("audio/mpeg"))
+            );
+            feed.push_str("</item>\n");
+        }
+        feed.push_str("</channel>\n</rss>\n");
+        Ok(vec![Artifact {
+            path: self.path.clone(),
+            contents: feed.into(),
+        }])
+    }
+}
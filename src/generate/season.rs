@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+
+use super::{i18n::Labels, Artifact, Generator, Html, Rss};
+use crate::manifest::{slugify, Manifest};
+
+/// A page and an RSS feed per season of the episodes of a series
+///
+/// Songs with a `series` or `season` are grouped by both, in order of first appearance, and
+/// ordered by episode within a group. The files are named after the group, like
+/// `kitchen-tales-season-2.html` and `kitchen-tales-season-2.xml`.
+#[derive(Debug, Clone)]
+pub struct Seasons {
+    page: Html,
+}
+
+impl Seasons {
+    /// Resolve files referenced by the manifest, like lyrics, relative to `base`
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Self {
+            page: Html::default().base(base),
+        }
+    }
+
+    /// Render the pages like `page`, apart from their path and album archives
+    pub fn page(mut self, page: Html) -> Self {
+        self.page = page;
+        self
+    }
+}
+
+/// The episodes of a season, or of a series without seasons
+struct Season<'a> {
+    series: Option<&'a str>,
+    season: Option<u32>,
+    indices: Vec<usize>,
+}
+
+impl Season<'_> {
+    fn title(&self, manifest: &Manifest) -> String {
+        let series = self
+            .series
+            .or((!manifest.title.is_empty()).then_some(manifest.title.as_str()));
+        let labels = Labels::new(manifest);
+        match (series, self.season) {
+            (Some(series), Some(season)) => {
+                format!("{series}, {}", labels.format("season", season))
+            }
+            (None, Some(season)) => labels.format("season", season),
+            (Some(series), None) => series.to_string(),
+            (None, None) => unreachable!("seasons have a series or season"),
+        }
+    }
+
+    /// The file name of the page and feed, without extension
+    fn stem(&self) -> String {
+        let series = self.series.map(slugify).filter(|slug| !slug.is_empty());
+        match (series, self.season) {
+            (Some(series), Some(season)) => format!("{series}-season-{season}"),
+            (None, Some(season)) => format!("season-{season}"),
+            (Some(series), None) => series,
+            (None, None) => "series".to_string(),
+        }
+    }
+}
+
+/// The seasons of `manifest`, in order of first appearance
+fn seasons(manifest: &Manifest) -> Vec<Season<'_>> {
+    let mut seasons: Vec<Season> = Vec::new();
+    for (index, song) in manifest.songs.iter().enumerate() {
+        let (series, season) = (song.series.as_deref(), song.season);
+        if series.is_none() && season.is_none() {
+            continue;
+        }
+        match seasons
+            .iter_mut()
+            .find(|s| s.series == series && s.season == season)
+        {
+            Some(season) => season.indices.push(index),
+            None => seasons.push(Season {
+                series,
+                season,
+                indices: vec![index],
+            }),
+        }
+    }
+    for season in &mut seasons {
+        season
+            .indices
+            .sort_by(|&a, &b| manifest.songs[a].cmp_episodes(&manifest.songs[b]));
+    }
+    seasons
+}
+
+impl Generator for Seasons {
+    fn name(&self) -> &str {
+        "seasons"
+    }
+
+    fn generate(&self, manifest: &Manifest, urls: &[String]) -> anyhow::Result<Vec<Artifact>> {
+        let mut artifacts = Vec::new();
+        for season in seasons(manifest) {
+            let episodes = Manifest {
+                title: season.title(manifest),
+                songs: season
+                    .indices
+                    .iter()
+                    .map(|&index| manifest.songs[index].clone())
+                    .collect(),
+                ..manifest.clone()
+            };
+            let urls = season
+                .indices
+                .iter()
+                .map(|&index| urls[index].clone())
+                .collect::<Vec<_>>();
+            let stem = season.stem();
+            let page = self
+                .page
+                .clone()
+                .path(format!("{stem}.html"))
+                .archives(false);
+            artifacts.extend(page.generate(&episodes, &urls)?);
+            artifacts.extend(Rss::new(format!("{stem}.xml")).generate(&episodes, &urls)?);
+        }
+        Ok(artifacts)
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use super::{Artifact, Generator, Json};
+use crate::manifest::Manifest;
+
+/// The path of the data file, relative to the site root
+const DATA: &str = "data/tracks.json";
+
+const HUGO_SHORTCODE: &str = r#"{{ with .Site.Data.tracks }}
+<div class="tracks">
+  {{ range .tracks }}
+  <div class="track">
+    <h3>{{ .name }}</h3>
+    {{ with .artist }}<p class="artist">{{ . }}</p>{{ end }}
+    <audio controls preload="none"><source src="{{ .url }}"{{ with .mime }} type="{{ . }}"{{ end }}></audio>
+  </div>
+  {{ end }}
+</div>
+{{ end }}
+"#;
+
+const ZOLA_SHORTCODE: &str = r#"{% set data = load_data(path="data/tracks.json") %}
+<div class="tracks">
+  {% for track in data.tracks %}
+  <div class="track">
+    <h3>{{ track.name }}</h3>
+    {% if track.artist %}<p class="artist">{{ track.artist }}</p>{% endif %}
+    <audio controls preload="none"><source src="{{ track.url }}"{% if track.mime %} type="{{ track.mime }}"{% endif %}></audio>
+  </div>
+  {% endfor %}
+</div>
+"#;
+
+/// A static site generator to export to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SiteKind {
+    Hugo,
+    Zola,
+}
+
+/// The tracklist as data file of a Hugo or Zola site, with an optional `tracks` shortcode
+///
+/// Paths are relative to the site root.
+#[derive(Debug, Clone)]
+pub struct Site {
+    kind: SiteKind,
+    shortcode: bool,
+}
+
+impl Site {
+    pub fn new(kind: SiteKind) -> Self {
+        Self {
+            kind,
+            shortcode: false,
+        }
+    }
+
+    /// Also emit a `tracks` shortcode rendering the data file
+    pub fn shortcode(mut self, shortcode: bool) -> Self {
+        self.shortcode = shortcode;
+        self
+    }
+}
+
+impl Generator for Site {
+    fn name(&self) -> &str {
+        match self.kind {
+            SiteKind::Hugo => "hugo",
+            SiteKind::Zola => "zola",
+        }
+    }
+
+    fn generate(&self, manifest: &Manifest, urls: &[String]) -> anyhow::Result<Vec<Artifact>> {
+        let mut artifacts = Json::new(DATA).generate(manifest, urls)?;
+        if self.shortcode {
+            let (path, template) = match self.kind {
+                SiteKind::Hugo => ("layouts/shortcodes/tracks.html", HUGO_SHORTCODE),
+                SiteKind::Zola => ("templates/shortcodes/tracks.html", ZOLA_SHORTCODE),
+            };
+            artifacts.push(Artifact {
+                path: PathBuf::from(path),
+                contents: template.to_string().into(),
+            });
+        }
+        Ok(artifacts)
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::Context;
+use std::path::PathBuf;
+
+use super::{escape, Artifact, Generator};
+use crate::manifest::Manifest;
+
+/// A `sitemap.xml` listing the page at the manifest `url`
+#[derive(Debug, Clone)]
+pub struct Sitemap {
+    path: PathBuf,
+}
+
+impl Sitemap {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Default for Sitemap {
+    fn default() -> Self {
+        Self::new("sitemap.xml")
+    }
+}
+
+impl Generator for Sitemap {
+    fn name(&self) -> &str {
+        "sitemap"
+    }
+
+    fn generate(&self, manifest: &Manifest, _urls: &[String]) -> anyhow::Result<Vec<Artifact>> {
+        let url = manifest
+            .url
+            .as_deref()
+            .context("A sitemap needs the page `url` in the manifest")?;
+        let sitemap = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n\
+             <url><loc>{}</loc></url>\n\
+             </urlset>\n",
+            escape(url)
+        );
+        Ok(vec![Artifact {
+            path: self.path.clone(),
+            contents: sitemap.into(),
+        }])
+    }
+}
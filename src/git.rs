@@ -0,0 +1,84 @@
+//! Recording manifest changes in the git repository containing the manifest
+
+use anyhow::Context;
+use git2::{Index, IndexEntry, IndexTime, Oid, Repository};
+use std::path::Path;
+
+/// Commit the manifest at `path` to its repository with `message`, returning the new commit
+///
+/// Only the manifest is committed, other staged changes stay staged. Nothing is committed if
+/// the manifest is unchanged since the last commit.
+pub fn commit(path: &Path, message: &str) -> anyhow::Result<Option<Oid>> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", path.display()))?;
+    let directory = path.parent().unwrap_or(Path::new("/"));
+    let repo = Repository::discover(directory)
+        .with_context(|| format!("{} is not in a git repository", path.display()))?;
+    let workdir = repo
+        .workdir()
+        .context("Cannot commit to a bare repository")?
+        .canonicalize()
+        .context("Failed to resolve the repository")?;
+    let relative = path
+        .strip_prefix(&workdir)
+        .context("Manifest is outside the repository")?;
+    let contents =
+        std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    // Build the tree of the commit from the last one, to leave other changes out
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit().context("Failed to read HEAD")?),
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
+        Err(e) => return Err(e).context("Failed to read HEAD"),
+    };
+    let mut tree = Index::new()?;
+    if let Some(parent) = &parent {
+        tree.read_tree(&parent.tree()?)?;
+    }
+    let relative = relative.to_string_lossy().replace('\\', "/");
+    let entry = IndexEntry {
+        ctime: IndexTime::new(0, 0),
+        mtime: IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode: 0o100644,
+        uid: 0,
+        gid: 0,
+        file_size: contents.len() as u32,
+        id: repo.blob(&contents).context("Failed to store manifest")?,
+        flags: relative.len().min(0xfff) as u16,
+        flags_extended: 0,
+        path: relative.clone().into_bytes(),
+    };
+    tree.add(&entry).context("Failed to stage manifest")?;
+    let tree = repo.find_tree(tree.write_tree_to(&repo)?)?;
+    if parent
+        .as_ref()
+        .is_some_and(|parent| parent.tree_id() == tree.id())
+    {
+        tracing::info!("{relative} is unchanged, not committing");
+        return Ok(None);
+    }
+
+    let signature = repo
+        .signature()
+        .context("Failed to sign the commit, set user.name and user.email in the git config")?;
+    let parents = parent.iter().collect::<Vec<_>>();
+    let commit = repo
+        .commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .context("Failed to commit manifest")?;
+    // Stage the committed manifest, so it does not show as changed
+    let mut index = repo.index()?;
+    index.add_path(Path::new(&relative))?;
+    index.write()?;
+    tracing::info!("Committed {relative}: {message}");
+    Ok(Some(commit))
+}
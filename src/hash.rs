@@ -0,0 +1,21 @@
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use std::{fmt::Write, fs::File, io, path::Path};
+
+/// The hex-encoded SHA-256 hash of the file at `path`
+pub fn sha256_file(path: &Path) -> anyhow::Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Lowercase hex encoding of `bytes`
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
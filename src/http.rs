@@ -0,0 +1,134 @@
+//! How to request songs from the servers hosting them, like behind basic auth or a CDN
+//! blocking unknown user agents
+
+use anyhow::{bail, Context};
+use lychee_lib::{ClientBuilder, DEFAULT_USER_AGENT};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, USER_AGENT};
+use std::str::FromStr;
+
+/// An extra header sent with every request, parsed from `Name: value`
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub name: HeaderName,
+    pub value: HeaderValue,
+}
+
+impl FromStr for Header {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, value)) = s.split_once(':') else {
+            bail!("Invalid header {s}, expected `Name: value`");
+        };
+        Ok(Self {
+            name: name
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid header name {name}"))?,
+            value: value
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid value of header {name}"))?,
+        })
+    }
+}
+
+/// Credentials for HTTP basic auth, parsed from `user:password` or just `user`
+#[derive(Clone)]
+pub struct BasicAuth {
+    pub user: String,
+    pub password: Option<String>,
+}
+
+impl FromStr for BasicAuth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (user, password) = match s.split_once(':') {
+            Some((user, password)) => (user, Some(password.to_string())),
+            None => (s, None),
+        };
+        if user.is_empty() {
+            bail!("Basic auth needs a user name, like `user:password`");
+        }
+        Ok(Self {
+            user: user.to_string(),
+            password,
+        })
+    }
+}
+
+// Keep passwords out of logs
+impl std::fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("user", &self.user)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The headers, user agent and credentials of requests to song URLs
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub headers: Vec<Header>,
+    pub user_agent: Option<String>,
+    pub basic_auth: Option<BasicAuth>,
+}
+
+impl Options {
+    /// The headers to send with every request, including the user agent and credentials
+    pub fn header_map(&self) -> anyhow::Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        for header in &self.headers {
+            headers.append(header.name.clone(), header.value.clone());
+        }
+        if let Some(user_agent) = &self.user_agent {
+            let value = user_agent.parse().context("Invalid user agent")?;
+            headers.insert(USER_AGENT, value);
+        }
+        if let Some(auth) = &self.basic_auth {
+            // Build the header like reqwest does, without sending a request
+            let request = reqwest::Client::new()
+                .get("http://localhost/")
+                .basic_auth(&auth.user, auth.password.as_ref())
+                .build()
+                .context("Invalid basic auth credentials")?;
+            if let Some(value) = request.headers().get(AUTHORIZATION) {
+                let mut value = value.clone();
+                value.set_sensitive(true);
+                headers.insert(AUTHORIZATION, value);
+            }
+        }
+        Ok(headers)
+    }
+
+    /// These options without credentials, for presigned URLs that carry their own
+    pub fn without_auth(&self) -> Self {
+        Self {
+            basic_auth: None,
+            ..self.clone()
+        }
+    }
+
+    /// A client sending these headers
+    pub fn client(&self) -> anyhow::Result<reqwest::Client> {
+        reqwest::Client::builder()
+            .default_headers(self.header_map()?)
+            .build()
+            .context("Failed to build HTTP client")
+    }
+
+    /// A link checker sending these headers
+    pub fn checker(&self) -> anyhow::Result<lychee_lib::Client> {
+        let mut headers = self.header_map()?;
+        // lychee sets the user agent on its own
+        headers.remove(USER_AGENT);
+        let user_agent = self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+        ClientBuilder::builder()
+            .custom_headers(headers)
+            .user_agent(user_agent)
+            .build()
+            .client()
+            .context("Failed to build link checker")
+    }
+}
@@ -0,0 +1,337 @@
+//! Looking up the canonical metadata of songs in MusicBrainz, by the AcoustID fingerprint of
+//! their audio or by their name, artist and album
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use std::{
+    fmt,
+    path::Path,
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use crate::manifest::Song;
+
+/// The environment variable holding the AcoustID application key for fingerprint lookups
+pub const ACOUSTID_KEY_VAR: &str = "TRACKINATOR_ACOUSTID_KEY";
+
+/// MusicBrainz asks clients to identify themselves
+const USER_AGENT: &str = concat!(
+    "trackinator/",
+    env!("CARGO_PKG_VERSION"),
+    " ( https://github.com/barafael/trackinator )"
+);
+
+/// The canonical metadata of a recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    /// The MusicBrainz recording ID
+    pub id: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub year: Option<i32>,
+    /// How well the recording matches, from 0 to 1
+    pub score: f64,
+}
+
+impl Metadata {
+    /// The fields of `song` this would change, as `(field, old, new)`
+    pub fn changes(&self, song: &Song) -> Vec<(&'static str, String, String)> {
+        let show = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        let mut changes = Vec::new();
+        if song.name != self.title {
+            changes.push(("name", song.name.clone(), self.title.clone()));
+        }
+        let fields = [
+            ("artist", song.artist.clone(), self.artist.clone()),
+            ("album", song.album.clone(), self.album.clone()),
+            (
+                "year",
+                song.year.map(|year| year.to_string()),
+                self.year.map(|year| year.to_string()),
+            ),
+        ];
+        for (field, old, new) in fields {
+            if new.is_some() && old != new {
+                changes.push((field, show(old), show(new)));
+            }
+        }
+        changes
+    }
+
+    /// Fill in the fields of `song` found in MusicBrainz
+    pub fn apply(&self, song: &mut Song) {
+        song.name = self.title.clone();
+        for (field, value) in [
+            (&mut song.artist, &self.artist),
+            (&mut song.album, &self.album),
+        ] {
+            if value.is_some() {
+                *field = value.clone();
+            }
+        }
+        if self.year.is_some() {
+            song.year = self.year;
+        }
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.title)?;
+        if let Some(artist) = &self.artist {
+            write!(f, " by {artist}")?;
+        }
+        if let Some(album) = &self.album {
+            write!(f, " on {album}")?;
+        }
+        if let Some(year) = self.year {
+            write!(f, " ({year})")?;
+        }
+        write!(
+            f,
+            ", {:.0}% match, https://musicbrainz.org/recording/{}",
+            self.score * 100.0,
+            self.id
+        )
+    }
+}
+
+/// The Chromaprint fingerprint of an audio file
+#[derive(Debug, Clone, Deserialize)]
+pub struct Fingerprint {
+    /// The play time in seconds
+    pub duration: f64,
+    pub fingerprint: String,
+}
+
+/// The fingerprint of the audio file at `path`, computed by `fpcalc` from Chromaprint
+pub fn fingerprint(path: &Path) -> anyhow::Result<Fingerprint> {
+    let output = Command::new("fpcalc")
+        .arg("-json")
+        .arg(path)
+        .output()
+        .context("Failed to run fpcalc, install Chromaprint")?;
+    if !output.status.success() {
+        bail!(
+            "fpcalc failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    serde_json::from_slice(&output.stdout).context("Invalid fpcalc output")
+}
+
+/// Waits between requests to stay below a rate limit
+#[derive(Debug)]
+struct RateLimit {
+    interval: Duration,
+    next: Option<Instant>,
+}
+
+impl RateLimit {
+    fn per_second(requests: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / requests,
+            next: None,
+        }
+    }
+
+    async fn wait(&mut self) {
+        if let Some(next) = self.next {
+            tokio::time::sleep_until(next.into()).await;
+        }
+        self.next = Some(Instant::now() + self.interval);
+    }
+}
+
+/// A client of MusicBrainz and AcoustID, within their rate limits of one and three requests
+/// per second
+#[derive(Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    acoustid_key: Option<String>,
+    musicbrainz: RateLimit,
+    acoustid: RateLimit,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recording {
+    id: String,
+    title: String,
+    /// Only in search results, from 0 to 100
+    score: Option<f64>,
+    #[serde(default, rename = "artist-credit")]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(rename = "first-release-date")]
+    first_release_date: Option<String>,
+    #[serde(default)]
+    releases: Vec<Release>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtistCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Search {
+    recordings: Vec<Recording>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Lookup {
+    status: String,
+    #[serde(default)]
+    results: Vec<LookupResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupResult {
+    score: f64,
+    #[serde(default)]
+    recordings: Vec<LookupRecording>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupRecording {
+    id: String,
+}
+
+impl Recording {
+    /// The metadata of this recording, preferring the release named `album`
+    fn metadata(self, album: Option<&str>, score: f64) -> Metadata {
+        let artist = self
+            .artist_credit
+            .iter()
+            .map(|credit| format!("{}{}", credit.name, credit.joinphrase))
+            .collect::<String>();
+        let release = self
+            .releases
+            .iter()
+            .find(|release| album.is_some_and(|album| release.title.eq_ignore_ascii_case(album)))
+            .or(self.releases.first());
+        Metadata {
+            id: self.id,
+            title: self.title,
+            artist: (!artist.is_empty()).then_some(artist),
+            album: release.map(|release| release.title.clone()),
+            year: self
+                .first_release_date
+                .and_then(|date| date.get(..4).and_then(|year| year.parse().ok())),
+            score,
+        }
+    }
+}
+
+impl Client {
+    /// A client looking up fingerprints with the AcoustID application key `acoustid_key`
+    pub fn new(acoustid_key: Option<String>) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .context("Failed to build HTTP client")?;
+        Ok(Self {
+            http,
+            acoustid_key,
+            musicbrainz: RateLimit::per_second(1),
+            acoustid: RateLimit::per_second(3),
+        })
+    }
+
+    /// The best match of the audio with `fingerprint`, if AcoustID knows it
+    pub async fn by_fingerprint(
+        &mut self,
+        fingerprint: &Fingerprint,
+        album: Option<&str>,
+    ) -> anyhow::Result<Option<Metadata>> {
+        let key = self.acoustid_key.as_deref().with_context(|| {
+            format!("Fingerprint lookups need an AcoustID application key in ${ACOUSTID_KEY_VAR}")
+        })?;
+        self.acoustid.wait().await;
+        let lookup: Lookup = self
+            .http
+            .post("https://api.acoustid.org/v2/lookup")
+            .form(&[
+                ("client", key),
+                ("meta", "recordingids"),
+                (
+                    "duration",
+                    &(fingerprint.duration.round() as u64).to_string(),
+                ),
+                ("fingerprint", &fingerprint.fingerprint),
+            ])
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context("Failed to query AcoustID")?
+            .json()
+            .await
+            .context("Invalid AcoustID response")?;
+        if lookup.status != "ok" {
+            bail!("AcoustID lookup failed with status {}", lookup.status);
+        }
+        let best = lookup
+            .results
+            .into_iter()
+            .filter(|result| !result.recordings.is_empty())
+            .max_by(|a, b| a.score.total_cmp(&b.score));
+        let Some(best) = best else {
+            return Ok(None);
+        };
+        let id = &best.recordings[0].id;
+        self.musicbrainz.wait().await;
+        let recording: Recording = self
+            .http
+            .get(format!("https://musicbrainz.org/ws/2/recording/{id}"))
+            .query(&[("inc", "artist-credits+releases"), ("fmt", "json")])
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context("Failed to query MusicBrainz")?
+            .json()
+            .await
+            .context("Invalid MusicBrainz response")?;
+        Ok(Some(recording.metadata(album, best.score)))
+    }
+
+    /// The best match of the name, artist and album of `song` in MusicBrainz, falling back to
+    /// the album `artist`
+    pub async fn by_tags(
+        &mut self,
+        song: &Song,
+        artist: Option<&str>,
+    ) -> anyhow::Result<Option<Metadata>> {
+        let quote = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut query = format!("recording:\"{}\"", quote(&song.name));
+        if let Some(artist) = song.artist.as_deref().or(artist) {
+            query.push_str(&format!(" AND artist:\"{}\"", quote(artist)));
+        }
+        if let Some(album) = &song.album {
+            query.push_str(&format!(" AND release:\"{}\"", quote(album)));
+        }
+        self.musicbrainz.wait().await;
+        let search: Search = self
+            .http
+            .get("https://musicbrainz.org/ws/2/recording")
+            .query(&[("query", query.as_str()), ("limit", "1"), ("fmt", "json")])
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context("Failed to query MusicBrainz")?
+            .json()
+            .await
+            .context("Invalid MusicBrainz response")?;
+        Ok(search.recordings.into_iter().next().map(|recording| {
+            let score = recording.score.unwrap_or(0.0) / 100.0;
+            recording.metadata(song.album.as_deref(), score)
+        }))
+    }
+}
@@ -0,0 +1,342 @@
+//! Converting playlists of other players, like M3U, XSPF or a spreadsheet, into songs
+
+use anyhow::{bail, Context};
+use chrono::Utc;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use url::Url;
+
+use crate::manifest::{decode_path, normalize_path, Manifest, Song};
+
+/// The format of a playlist to import
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// An M3U or M3U8 playlist, with `#EXTINF` titles and durations if present
+    M3u,
+    /// An XML Shareable Playlist Format playlist
+    Xspf,
+    /// A CSV file with a header row naming at least the `name` and `path` columns, and
+    /// optionally `album`, `artist` and `duration`
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "m3u" | "m3u8" => Ok(Format::M3u),
+            "xspf" => Ok(Format::Xspf),
+            "csv" => Ok(Format::Csv),
+            _ => bail!("Unknown playlist format {s}, expected one of m3u, m3u8, xspf, csv"),
+        }
+    }
+}
+
+impl Format {
+    /// The format of the playlist at `path`, by its extension
+    pub fn of(path: &Path) -> anyhow::Result<Self> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .with_context(|| format!("Cannot tell the format of {}, pass it", path.display()))?;
+        extension.parse()
+    }
+}
+
+/// Where an entry of a playlist is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Url(String),
+    /// A local file, relative to the playlist unless absolute
+    Path(PathBuf),
+}
+
+impl Location {
+    /// The location written as `text`, a URL or a plain path
+    fn parse(text: &str) -> anyhow::Result<Self> {
+        match Url::parse(text) {
+            Ok(url) if url.scheme() == "file" => url
+                .to_file_path()
+                .map(Location::Path)
+                .map_err(|()| anyhow::anyhow!("Invalid file URL {text}")),
+            // Windows paths like `C:\Music` parse as URLs with a one-letter scheme
+            Ok(url) if url.scheme().len() > 1 => Ok(Location::Url(text.to_string())),
+            _ => Ok(Location::Path(text.into())),
+        }
+    }
+}
+
+/// An entry of a playlist
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub location: Location,
+    pub name: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// The play time in seconds
+    pub duration: Option<f64>,
+}
+
+impl Entry {
+    fn at(location: Location) -> Self {
+        Self {
+            location,
+            name: None,
+            artist: None,
+            album: None,
+            duration: None,
+        }
+    }
+}
+
+/// A playlist read from another format
+#[derive(Debug, Clone, Default)]
+pub struct Playlist {
+    pub title: Option<String>,
+    pub entries: Vec<Entry>,
+}
+
+/// Read the playlist `text` in `format`
+pub fn parse(text: &str, format: Format) -> anyhow::Result<Playlist> {
+    match format {
+        Format::M3u => parse_m3u(text),
+        Format::Xspf => parse_xspf(text),
+        Format::Csv => parse_csv(text),
+    }
+}
+
+fn parse_m3u(text: &str) -> anyhow::Result<Playlist> {
+    let mut playlist = Playlist::default();
+    let mut info = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(title) = line.strip_prefix("#PLAYLIST:") {
+            playlist.title = Some(title.trim().to_string());
+        } else if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            // `#EXTINF:<seconds>,<artist> - <title>`, the duration being -1 if unknown
+            let (duration, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+            let duration = duration
+                .split_whitespace()
+                .next()
+                .and_then(|duration| duration.parse::<f64>().ok())
+                .filter(|duration| *duration > 0.0);
+            let (artist, name) = match title.split_once(" - ") {
+                Some((artist, name)) => (Some(artist.trim().to_string()), name),
+                None => (None, title),
+            };
+            let name = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+            info = Some((duration, artist, name));
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let mut entry = Entry::at(Location::parse(line)?);
+            if let Some((duration, artist, name)) = info.take() {
+                entry.duration = duration;
+                entry.artist = artist;
+                entry.name = name;
+            }
+            playlist.entries.push(entry);
+        }
+    }
+    Ok(playlist)
+}
+
+fn parse_xspf(text: &str) -> anyhow::Result<Playlist> {
+    let document = roxmltree::Document::parse(text).context("Invalid XSPF playlist")?;
+    let root = document.root_element();
+    let child = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|child| child.has_tag_name(name))
+            .and_then(|child| child.text())
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+    };
+    let mut playlist = Playlist {
+        title: child(root, "title"),
+        ..Default::default()
+    };
+    let tracks = root
+        .children()
+        .filter(|node| node.has_tag_name("trackList"))
+        .flat_map(|list| list.children().filter(|node| node.has_tag_name("track")));
+    for track in tracks {
+        let Some(location) = child(track, "location") else {
+            tracing::warn!("Skipping track without location");
+            continue;
+        };
+        // Relative locations are URI references, percent-encoded like URLs
+        let location = match Location::parse(&location)? {
+            Location::Path(path) => Location::Path(decode_path(&path.to_string_lossy())?),
+            url => url,
+        };
+        playlist.entries.push(Entry {
+            location,
+            name: child(track, "title"),
+            artist: child(track, "creator"),
+            album: child(track, "album"),
+            duration: child(track, "duration")
+                .and_then(|milliseconds| milliseconds.parse::<f64>().ok())
+                .map(|milliseconds| milliseconds / 1000.0),
+        });
+    }
+    Ok(playlist)
+}
+
+fn parse_csv(text: &str) -> anyhow::Result<Playlist> {
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+    let headers = reader
+        .headers()
+        .context("Invalid CSV header row")?
+        .iter()
+        .map(|header| header.trim().to_lowercase())
+        .collect::<Vec<_>>();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let (Some(name), Some(path)) = (column("name"), column("path")) else {
+        bail!("CSV playlists need a name and a path column");
+    };
+    let (album, artist, duration) = (column("album"), column("artist"), column("duration"));
+    let mut playlist = Playlist::default();
+    for (row, record) in reader.records().enumerate() {
+        let record = record.with_context(|| format!("Invalid CSV row {}", row + 2))?;
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| record.get(index))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        let location = field(Some(path)).with_context(|| format!("No path in row {}", row + 2))?;
+        playlist.entries.push(Entry {
+            location: Location::parse(&location)?,
+            name: field(Some(name)),
+            artist: field(artist),
+            album: field(album),
+            duration: field(duration).and_then(|duration| duration.parse().ok()),
+        });
+    }
+    Ok(playlist)
+}
+
+/// The common base of the most frequent origin among `urls`, like
+/// `https://cdn.example.com/music/`
+pub fn infer_prefix<'a>(urls: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let mut origins = BTreeMap::<String, Vec<&str>>::new();
+    for url in urls {
+        let Ok(parsed) = Url::parse(url) else {
+            continue;
+        };
+        let origin = parsed.origin().ascii_serialization();
+        origins.entry(origin).or_default().push(url);
+    }
+    let (origin, urls) = origins.into_iter().max_by_key(|(_, urls)| urls.len())?;
+    let mut prefix = url_directory(urls[0]);
+    for url in &urls[1..] {
+        let common = prefix
+            .char_indices()
+            .zip(url.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(prefix.len().min(url.len()), |((index, _), _)| index);
+        prefix = url_directory(&prefix[..common]);
+    }
+    (prefix.len() > origin.len()).then(|| prefix.to_string())
+}
+
+/// `url` up to and including its last `/`
+fn url_directory(url: &str) -> &str {
+    url.rfind('/').map_or("", |slash| &url[..=slash])
+}
+
+/// Append the entries of `playlist`, read from the file `input`, to `manifest` in
+/// `directory`, returning the indices of the new songs
+///
+/// An empty manifest without prefix gets the common base of the URLs as its prefix, other URLs
+/// keep theirs as song prefix. Local paths are made relative to `directory` where they are
+/// below it. Songs without name are named after their file.
+pub fn import(
+    manifest: &mut Manifest,
+    playlist: Playlist,
+    input: &Path,
+    directory: &Path,
+) -> anyhow::Result<Vec<usize>> {
+    if manifest.songs.is_empty() && manifest.prefix.is_empty() {
+        let urls = playlist
+            .entries
+            .iter()
+            .filter_map(|entry| match &entry.location {
+                Location::Url(url) => Some(url.as_str()),
+                Location::Path(_) => None,
+            });
+        if let Some(prefix) = infer_prefix(urls) {
+            tracing::info!("Using the prefix {prefix}");
+            manifest.prefix = prefix;
+        }
+    }
+    let base = canonical(directory)?;
+    let playlist_directory = canonical(input.parent().unwrap_or(Path::new("")))?;
+    let start = manifest.songs.len();
+    for entry in playlist.entries {
+        let (prefix, path) = match entry.location {
+            Location::Url(url) => {
+                let (prefix, rest) = match url.strip_prefix(&manifest.prefix) {
+                    Some(rest) if !manifest.prefix.is_empty() => (None, rest),
+                    _ => {
+                        let prefix = url_directory(&url);
+                        (Some(prefix.to_string()), &url[prefix.len()..])
+                    }
+                };
+                let path = if manifest.encoded {
+                    rest.into()
+                } else {
+                    decode_path(rest)?
+                };
+                (prefix, path)
+            }
+            Location::Path(path) => {
+                let path = playlist_directory.join(path);
+                let path = match path.strip_prefix(&base) {
+                    Ok(relative) => normalize_path(relative)?,
+                    Err(_) => {
+                        tracing::warn!("Keeping {}, it is outside the manifest", path.display());
+                        path
+                    }
+                };
+                // Local files are not below a remote prefix
+                let prefix = (!manifest.prefix.is_empty()).then(String::new);
+                (prefix, path)
+            }
+        };
+        let name = entry.name.unwrap_or_else(|| {
+            path.file_stem().map_or_else(
+                || path.display().to_string(),
+                |stem| stem.to_string_lossy().into_owned(),
+            )
+        });
+        let artist = entry
+            .artist
+            .filter(|artist| Some(artist) != manifest.artist.as_ref());
+        manifest.songs.push(Song {
+            name,
+            path,
+            prefix,
+            artist,
+            album: entry.album,
+            duration: entry.duration,
+            added: Some(Utc::now()),
+            ..Default::default()
+        });
+    }
+    Ok((start..manifest.songs.len()).collect())
+}
+
+/// `directory` resolved, the working directory if empty
+fn canonical(directory: &Path) -> anyhow::Result<PathBuf> {
+    let directory = if directory.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        directory
+    };
+    directory
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", directory.display()))
+}
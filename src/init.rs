@@ -0,0 +1,126 @@
+//! Scaffolding new projects from presets
+
+use anyhow::bail;
+use std::{path::Path, str::FromStr};
+
+use crate::manifest::{Manifest, Numbering, NumberingScope, Song, Theme};
+
+/// The example stylesheet written next to the manifest of a preset
+pub const STYLESHEET_FILE: &str = "theme.css";
+
+/// The example stylesheet, overriding the built-in theme
+pub const STYLESHEET: &str =
+    "/* Included after the built-in theme, so rules here take precedence */
+
+:root {
+  --accent: #e0457b;
+}
+
+body {
+  max-width: 48em;
+  margin: 0 auto;
+  padding: 0 1em;
+}
+
+.song h3 {
+  margin-bottom: 0.3em;
+}
+";
+
+/// A starting point for a new manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// Albums of a single artist, numbered per album
+    Band,
+    /// Episodes of a series, by season
+    Podcast,
+    /// A compilation of many artists, tagged and numbered throughout
+    Archive,
+}
+
+impl FromStr for Preset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "band" => Ok(Preset::Band),
+            "podcast" => Ok(Preset::Podcast),
+            "archive" => Ok(Preset::Archive),
+            _ => bail!("Unknown preset {s}, expected one of band, podcast, archive"),
+        }
+    }
+}
+
+impl Preset {
+    /// The manifest of this preset with `title` and `prefix`, with an example song
+    pub fn manifest(self, title: String, prefix: String) -> Manifest {
+        let manifest = Manifest {
+            title,
+            prefix,
+            stylesheet: Some(STYLESHEET_FILE.into()),
+            ..Default::default()
+        };
+        match self {
+            Preset::Band => Manifest {
+                artist: Some("The Band".to_string()),
+                theme: Some(Theme::Dark),
+                numbering: Some(Numbering {
+                    scope: NumberingScope::Album,
+                    width: 2,
+                    ..Default::default()
+                }),
+                songs: vec![Song {
+                    name: "First Song".to_string(),
+                    path: "audio/first-song.mp3".into(),
+                    album: Some("First Album".to_string()),
+                    ..Default::default()
+                }],
+                ..manifest
+            },
+            Preset::Podcast => Manifest {
+                description: Some("What the show is about".to_string()),
+                artist: Some("The Hosts".to_string()),
+                theme: Some(Theme::Auto),
+                songs: vec![Song {
+                    name: "Pilot".to_string(),
+                    path: "episodes/s01e01.mp3".into(),
+                    series: Some(manifest.title.clone()),
+                    season: Some(1),
+                    episode: Some(1),
+                    ..Default::default()
+                }],
+                ..manifest
+            },
+            Preset::Archive => Manifest {
+                various_artists: true,
+                theme: Some(Theme::Light),
+                numbering: Some(Numbering {
+                    width: 3,
+                    ..Default::default()
+                }),
+                songs: vec![Song {
+                    name: "Recording".to_string(),
+                    path: "recordings/recording.flac".into(),
+                    artist: Some("Some Artist".to_string()),
+                    tags: vec!["live".to_string()],
+                    ..Default::default()
+                }],
+                ..manifest
+            },
+        }
+    }
+}
+
+/// The config file pointing at the manifest `manifest` next to it
+pub fn config(manifest: &Path) -> String {
+    let manifest = manifest.to_string_lossy().replace('\\', "\\\\");
+    format!(
+        "# Defaults for trackinator commands run in this directory or below it
+manifest = \"{manifest}\"
+output = \"index.html\"
+
+[check]
+verify = false
+"
+    )
+}
@@ -0,0 +1,37 @@
+//! Generate static html pages with JSON metadata
+//!
+//! The [`generate::Generator`] trait is the extension point for custom outputs.
+
+pub mod atomic;
+pub mod benchmark;
+pub mod changelog;
+pub mod check;
+pub mod compare;
+pub mod config;
+pub mod deploy;
+pub mod download;
+pub mod error;
+pub mod filter;
+pub mod generate;
+pub mod git;
+pub mod hash;
+pub mod http;
+pub mod identify;
+pub mod import;
+pub mod init;
+pub mod lint;
+pub mod lock;
+pub mod lrc;
+pub mod manifest;
+pub mod merge;
+pub mod normalize;
+pub mod notify;
+pub mod probe;
+pub mod progress;
+pub mod remote;
+pub mod s3;
+pub mod serve;
+pub mod split;
+pub mod stats;
+pub mod token;
+pub mod transcode;
@@ -0,0 +1,111 @@
+//! Finding problems spanning the manifests of a workspace
+
+use anyhow::Context;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+use url::Url;
+
+use crate::manifest::{song_url, Manifest};
+
+/// A song of one of the linted manifests
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub manifest: PathBuf,
+    pub index: usize,
+    pub name: String,
+}
+
+/// Songs that are the same file, and would be uploaded twice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Duplicate {
+    /// Songs at the same local path or URL
+    Location {
+        location: String,
+        entries: Vec<Entry>,
+    },
+    /// Songs at different locations with the same content hash
+    Hash { hash: String, entries: Vec<Entry> },
+}
+
+impl fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries = match self {
+            Duplicate::Location { location, entries } => {
+                writeln!(f, "{location} is in several songs:")?;
+                entries
+            }
+            Duplicate::Hash { hash, entries } => {
+                writeln!(f, "Songs with the same content (SHA-256 {hash}):")?;
+                entries
+            }
+        };
+        for entry in entries {
+            writeln!(
+                f,
+                "  {} #{} ({})",
+                entry.manifest.display(),
+                entry.index,
+                entry.name
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The songs of `manifests`, read from their paths, that are the same file as another one,
+/// by local path or URL and by recorded `hash`
+///
+/// Local paths are compared after resolving them against the directory of their manifest.
+pub fn duplicates(manifests: &[(PathBuf, Manifest)]) -> anyhow::Result<Vec<Duplicate>> {
+    let mut locations = BTreeMap::<String, Vec<Entry>>::new();
+    let mut hashes = BTreeMap::<String, Vec<(String, Entry)>>::new();
+    for (path, manifest) in manifests {
+        let base = path.parent().unwrap_or(Path::new(""));
+        let base = if base.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            base
+        };
+        let base = base
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {}", base.display()))?;
+        for (index, song) in manifest.songs.iter().enumerate() {
+            let url = song_url(manifest, song)?;
+            let location = match Url::parse(&url) {
+                Ok(_) => url,
+                Err(_) => base.join(&song.path).display().to_string(),
+            };
+            let entry = Entry {
+                manifest: path.clone(),
+                index,
+                name: song.name.clone(),
+            };
+            if let Some(hash) = &song.hash {
+                hashes
+                    .entry(hash.to_ascii_lowercase())
+                    .or_default()
+                    .push((location.clone(), entry.clone()));
+            }
+            locations.entry(location).or_default().push(entry);
+        }
+    }
+
+    let mut duplicates = locations
+        .into_iter()
+        .filter(|(_, entries)| entries.len() > 1)
+        .map(|(location, entries)| Duplicate::Location { location, entries })
+        .collect::<Vec<_>>();
+    for (hash, mut entries) in hashes {
+        // Songs at the same location are reported as such already
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        entries.dedup_by(|(a, _), (b, _)| a == b);
+        if entries.len() > 1 {
+            let entries = entries.into_iter().map(|(_, entry)| entry).collect();
+            duplicates.push(Duplicate::Hash { hash, entries });
+        }
+    }
+    Ok(duplicates)
+}
@@ -0,0 +1,45 @@
+//! Advisory locking of manifests across concurrent invocations
+
+use anyhow::Context;
+use fs2::FileExt;
+use std::{
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+/// An exclusive lock on a manifest, released when dropped
+#[derive(Debug)]
+pub struct Lock {
+    file: File,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// Lock the manifest at `path` exclusively, waiting for other holders to release it
+///
+/// The lock is taken on a `.<name>.lock` file next to the manifest, since saving replaces the
+/// manifest itself by renaming.
+pub fn exclusive(path: &Path) -> anyhow::Result<Lock> {
+    let lock_path = lock_path(path);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open lock file {}", lock_path.display()))?;
+    if file.try_lock_exclusive().is_err() {
+        tracing::info!("Waiting for lock on {}", path.display());
+        file.lock_exclusive()
+            .with_context(|| format!("Failed to lock {}", path.display()))?;
+    }
+    Ok(Lock { file })
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.lock"))
+}
@@ -0,0 +1,104 @@
+//! Parsing of `.lrc` timed lyrics
+
+/// A line of lyrics and the time in seconds it starts at
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub time: f64,
+    pub text: String,
+}
+
+/// Parse the timed lines of an LRC file, sorted by time
+///
+/// Lines may carry several `[mm:ss.xx]` timestamps. ID tags like `[ar:Artist]`, an `[offset:ms]`
+/// tag aside, and untimed lines are ignored.
+pub fn parse(lrc: &str) -> Vec<Line> {
+    let mut offset = 0.0;
+    let mut lines = Vec::new();
+    for raw in lrc.lines() {
+        let mut rest = raw.trim();
+        let mut times = Vec::new();
+        while let Some((tag, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+            if let Some(time) = timestamp(tag) {
+                times.push(time);
+            } else if let Some(ms) = tag.strip_prefix("offset:") {
+                offset = ms.trim().parse::<f64>().unwrap_or_default() / 1000.0;
+            }
+            rest = after;
+        }
+        let text = rest.trim();
+        lines.extend(times.into_iter().map(|time| Line {
+            time,
+            text: text.to_string(),
+        }));
+    }
+    // A positive offset shifts the lyrics up, i.e. makes them appear sooner
+    for line in &mut lines {
+        line.time = (line.time - offset).max(0.0);
+    }
+    lines.sort_by(|a, b| a.time.total_cmp(&b.time));
+    lines
+}
+
+/// Parse `mm:ss`, `mm:ss.xx`, or `mm:ss:xx` into seconds
+fn timestamp(tag: &str) -> Option<f64> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes: u32 = minutes.parse().ok()?;
+    let seconds: f64 = match seconds.split_once(':') {
+        Some((seconds, hundredths)) => format!("{seconds}.{hundredths}").parse().ok()?,
+        None => seconds.parse().ok()?,
+    };
+    Some(f64::from(minutes) * 60.0 + seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(lrc: &str) -> Vec<(f64, String)> {
+        parse(lrc)
+            .into_iter()
+            .map(|line| (line.time, line.text))
+            .collect()
+    }
+
+    #[test]
+    fn timestamp_formats() {
+        assert_eq!(timestamp("01:02"), Some(62.0));
+        assert_eq!(timestamp("01:02.50"), Some(62.5));
+        assert_eq!(timestamp("01:02:50"), Some(62.5));
+        assert_eq!(timestamp("ar:Artist"), None);
+        assert_eq!(timestamp("01:xx"), None);
+    }
+
+    #[test]
+    fn several_timestamps_on_a_line() {
+        assert_eq!(
+            times("[00:10.00]Verse\n[00:05.00][00:20.00]Chorus"),
+            [
+                (5.0, "Chorus".to_string()),
+                (10.0, "Verse".to_string()),
+                (20.0, "Chorus".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn offset_shifts_lines_sooner() {
+        assert_eq!(
+            times("[offset:+500]\n[00:00.20]First\n[00:02.00]Second"),
+            [(0.0, "First".to_string()), (1.5, "Second".to_string())]
+        );
+        assert_eq!(
+            times("[offset:-1000]\n[00:02.00]Later"),
+            [(3.0, "Later".to_string())]
+        );
+    }
+
+    #[test]
+    fn id_tags_and_untimed_lines_are_ignored() {
+        assert_eq!(
+            times("[ar:Artist]\n[ti:Title]\nUntimed\n[00:01:00]  Text  "),
+            [(1.0, "Text".to_string())]
+        );
+    }
+}
@@ -1,26 +1,139 @@
 use anyhow::Context;
-use clap::Parser;
-use laby::{html, iter, render};
-use lychee_lib::Response;
-use serde::{Deserialize, Serialize};
-use std::{fs::File, io::BufReader, path::PathBuf, process};
+use chrono::{DateTime, Utc};
+use clap::{
+    ArgAction, ArgGroup, Args, Command, CommandFactory, FromArgMatches, Parser, Subcommand,
+    ValueEnum,
+};
+use clap_complete::Shell;
+use std::{
+    io::Write,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process,
+};
+use tracing::Level;
+use url::Url;
+
+use trackinator::{
+    atomic, benchmark, changelog, check, compare,
+    config::{self, Config},
+    deploy, download, error,
+    filter::{self, Filter},
+    generate, git, hash, http, identify, import, init, lint, lock,
+    manifest::{
+        decode_path, normalize_path, plain_song_url, song_url, Manifest, Song, Storage, Theme,
+    },
+    merge, normalize, notify, probe, progress, remote, s3, serve, split, stats, transcode,
+};
 
 #[derive(Debug, Parser)]
 pub enum Action {
-    /// Generate from `manifest` the HTML `output`
+    /// Generate from `manifest` the HTML `output` and other formats next to it
+    ///
+    /// Songs past their `unpublish_at` are left out.
     Generate {
         /// The JSON input file
-        #[arg(long, default_value = "tracks.json")]
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
         manifest: PathBuf,
 
-        /// The file to write the output to
-        #[arg(long, default_value = "index.html")]
+        /// The file to write the HTML output to
+        #[arg(long, env = "TRACKINATOR_OUTPUT", default_value = "index.html")]
         output: PathBuf,
+
+        /// The output formats to generate: `html`, `rss` (`feed.xml`), `json`
+        /// (`tracks-index.json`), `md` (a Markdown track listing in `tracks.md`), `sitemap`
+        /// (`sitemap.xml`, needs the manifest `url`), `zip` (a ZIP of the local files per album
+        /// and `SHA256SUMS`, linked from the page), `seasons` (a page and feed per season of a
+        /// series)
+        #[arg(
+            long = "format",
+            visible_alias = "emit",
+            value_delimiter = ',',
+            default_value = "html"
+        )]
+        formats: Vec<String>,
+
+        /// How long presigned URLs for `s3://` songs stay valid, in seconds
+        #[arg(long, default_value_t = 24 * 60 * 60)]
+        expiry: u64,
+
+        /// Probe the local audio files for duration and size, caching them in the manifest
+        #[arg(long)]
+        probe: bool,
+
+        /// How many files to probe at the same time, by default one per CPU
+        #[arg(long, requires = "probe")]
+        jobs: Option<usize>,
+
+        /// The color scheme of the page, overriding the manifest `theme`: `dark`, `light`, or
+        /// `auto` to follow the visitor's preference
+        #[arg(long)]
+        theme: Option<Theme>,
+
+        /// Only include songs matching all these filters, like `tag=live` or `album!=Demos`
+        #[arg(long = "filter")]
+        filters: Vec<Filter>,
+
+        /// Only include the songs of this playlist of the manifest, in its order
+        #[arg(long)]
+        playlist: Option<String>,
+
+        /// Only include songs with this tag, like `--filter tag=<TAG>`
+        ///
+        /// The songs keep the URLs of the full page, so one manifest can feed several pages.
+        #[arg(long, value_name = "TAG")]
+        only_tag: Vec<String>,
+
+        /// Only include songs of this album, like `--filter album=<ALBUM>`
+        #[arg(long, value_name = "ALBUM")]
+        only_album: Vec<String>,
+
+        /// Generate pages without any JavaScript, for e-readers and restrictive browsers
+        ///
+        /// All tracks remain playable; only playing them one after another and highlighting
+        /// synced lyrics are left out.
+        #[arg(long)]
+        no_js: bool,
+
+        /// The order of the songs on the page, instead of that of the manifest: by `name`,
+        /// newest `added` first, or shortest `duration` first
+        #[arg(long, value_enum)]
+        sort: Option<PageOrder>,
+
+        /// Badge the songs added in the last `DAYS` days as new
+        #[arg(long, value_name = "DAYS")]
+        highlight_recent: Option<u32>,
+
+        /// Link each song for download next to its player, except those marked `no_download`
+        #[arg(long)]
+        download_links: bool,
+
+        /// Generate only the page, as one self-contained file to share; the audio stays linked
+        #[arg(long, conflicts_with = "formats")]
+        single_file: bool,
+
+        /// Embed the small local images of descriptions and lyrics into the single file
+        #[arg(long, requires = "single_file")]
+        embed_images: bool,
+
+        /// Leave out tracks that cannot be published, like those with unreadable lyrics,
+        /// reporting them at the end, instead of failing
+        #[arg(long)]
+        skip_invalid: bool,
+
+        /// Indent the HTML with each block element on a line of its own, for reviewable diffs
+        /// of a committed page
+        #[arg(long)]
+        pretty: bool,
     },
-    /// Add a track with `name` and `path` to `manifest`
+    /// Add a track with `name` and `path` to `manifest`, printing its index and ID
+    ///
+    /// `manifest` may also be the `http(s)` URL of a manifest on a web server accepting `PUT`,
+    /// like a WebDAV share, authenticated with `$TRACKINATOR_REMOTE_TOKEN` or
+    /// `$TRACKINATOR_REMOTE_USER` and `$TRACKINATOR_REMOTE_PASSWORD`.
     Add {
-        /// The `manifest` to modify
-        #[arg(long, default_value = "tracks.json")]
+        /// The `manifest` to modify, a file or URL
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
         manifest: PathBuf,
 
         /// The `name` of the new song
@@ -30,98 +143,1195 @@ pub enum Action {
         /// The `path` of the new song
         #[arg(long)]
         path: PathBuf,
+
+        /// The album of the new song
+        #[arg(long)]
+        album: Option<String>,
+
+        /// The artist of the new song, if different from the album artist
+        #[arg(long)]
+        artist: Option<String>,
+
+        /// The series of the new episode
+        #[arg(long)]
+        series: Option<String>,
+
+        /// The season of the new episode within its series
+        #[arg(long)]
+        season: Option<u32>,
+
+        /// The number of the new episode within its season
+        #[arg(long)]
+        episode: Option<u32>,
+
+        /// Tag the new song, repeatable or comma-separated
+        #[arg(long = "add-tag", value_delimiter = ',')]
+        tags: Vec<String>,
+
+        /// The MIME type of the new song, inferred from the file extension if omitted
+        #[arg(long)]
+        mime: Option<String>,
+
+        /// The prefix of the new song, overriding the manifest prefix
+        #[arg(long)]
+        prefix: Option<String>,
+
+        /// The `.lrc` file with time-synced lyrics of the new song
+        #[arg(long)]
+        lyrics_lrc: Option<PathBuf>,
+
+        /// When to stop publishing the new song, in RFC 3339 format
+        #[arg(long)]
+        unpublish_at: Option<DateTime<Utc>>,
     },
     /// Check an existing manifest:
     /// * Check each linked file is actually reachable
+    /// * Local paths are resolved relative to the manifest directory
+    /// * `s3://` objects are checked by HEAD requests with credentials from the environment
+    /// * With `--verify`, each file's recorded hash or size is compared as well
+    ///
+    /// Exits with 3 for network errors and timeouts, 4 for 4xx and 5 for 5xx statuses (the
+    /// highest applicable), or 1 if only verification failed.
     Check {
         /// The `manifest` to check
-        #[arg(long, default_value = "tracks.json")]
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
         manifest: PathBuf,
+
+        /// Download and verify files against their recorded hash, or compare their
+        /// `Content-Length` to the recorded size if there is no hash
+        #[arg(long, env = "TRACKINATOR_VERIFY")]
+        verify: bool,
+
+        /// Stop at the first unreachable file, like `--max-failures 1`
+        #[arg(long, conflicts_with = "max_failures")]
+        fail_fast: bool,
+
+        /// Stop once this many files are unreachable
+        #[arg(long)]
+        max_failures: Option<usize>,
+
+        /// Skip files found reachable (and verified, with `--verify`) within this many
+        /// seconds, as recorded in `.trackinator-check.json` next to the manifest
+        #[arg(long)]
+        max_age: Option<i64>,
+
+        /// Check all files regardless of `--max-age`
+        #[arg(long)]
+        force: bool,
+
+        /// Check again every this many seconds until stopped, notifying when failures
+        /// appear or clear
+        #[arg(long, value_name = "SECONDS")]
+        watch: Option<u64>,
+
+        /// How to request remote files: `head`, a 1-byte `range` request, or a full `get` by
+        /// the link checker
+        #[arg(long, default_value = "get")]
+        method: check::Method,
+
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Check every link of the generated HTML `output`, including styles, scripts and images
+    ///
+    /// Exits with the same codes as `check`.
+    CheckOutput {
+        /// The generated HTML file to check
+        #[arg(long, env = "TRACKINATOR_OUTPUT", default_value = "index.html")]
+        output: PathBuf,
+
+        /// The URL the page is deployed at, to resolve relative links against instead of
+        /// the local file
+        #[arg(long)]
+        base_url: Option<Url>,
+    },
+    /// Record the SHA-256 hash and size of each local file in `manifest`
+    Hash {
+        /// The `manifest` to update
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The directory containing the local files, by default the manifest directory
+        #[arg(long)]
+        local: Option<PathBuf>,
+
+        /// How many files to hash at the same time, by default one per CPU
+        #[arg(long)]
+        jobs: Option<usize>,
+    },
+    /// Compare the local audio files to their hosted copies and report tracks that drifted
+    CompareAudio {
+        /// The `manifest` to compare
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The directory containing the local masters, by default the manifest directory
+        #[arg(long)]
+        local: Option<PathBuf>,
+
+        /// Download and hash each hosted copy instead of only comparing sizes
+        #[arg(long)]
+        hash: bool,
+    },
+    /// Remove the `song` with the given index or ID from `manifest`
+    Remove {
+        /// The `manifest` to modify
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The index or ID of the song to remove
+        #[arg(long)]
+        song: String,
+    },
+    /// Change a song in `manifest`, printing its index and ID
+    Edit {
+        /// The `manifest` to modify
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The index or ID of the song to change
+        #[arg(long)]
+        song: String,
+
+        /// The new name of the song, which changes its ID
+        #[arg(long)]
+        name: Option<String>,
+
+        /// The new album of the song
+        #[arg(long)]
+        album: Option<String>,
+
+        /// The new artist of the song
+        #[arg(long)]
+        artist: Option<String>,
+
+        /// Tag the song, repeatable or comma-separated
+        #[arg(long = "add-tag", value_delimiter = ',')]
+        add_tags: Vec<String>,
+
+        /// Remove a tag from the song, repeatable or comma-separated
+        #[arg(long = "remove-tag", value_delimiter = ',')]
+        remove_tags: Vec<String>,
+    },
+    /// List the index, ID, name and tags of the songs in `manifest`, one per line
+    List {
+        /// The `manifest` to list
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// Only list songs matching all these filters, like `tag=live` or `album!=Demos`
+        #[arg(long = "filter")]
+        filters: Vec<Filter>,
+    },
+    /// List the tracks added, removed and renamed since an older version of `manifest`
+    ///
+    /// Songs are matched by prefix and path, so a song at the same location with a new name
+    /// counts as renamed.
+    Changelog {
+        /// The older version: a manifest file, or a git revision of `manifest` like `HEAD~1`
+        since: String,
+
+        /// The newer version
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+    },
+    /// Report songs that are the same file, by path, URL or hash, within and across the
+    /// manifests of the workspace
+    ///
+    /// Such duplicates are uploaded twice and may get conflicting metadata. Exits with 1 if there
+    /// are any.
+    Lint {
+        /// The manifests to lint, by default the `workspace` of the config file, or else
+        /// `manifest`
+        manifests: Vec<PathBuf>,
+
+        /// The manifest to lint without workspace
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+    },
+    /// Split `manifest` into one manifest per group of songs, printing the path of each
+    ///
+    /// The parts are written next to `manifest`, named like `tracks-summer-tour.json`, and can
+    /// be combined again with `merge`.
+    Split {
+        /// The `manifest` to split
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// How to group the songs: by `album`, by `tag` (songs with several tags end up in
+        /// several parts), or by `size` in consecutive parts of at most `--max-size`
+        #[arg(long)]
+        by: split::By,
+
+        /// The maximum total size of the songs of a part in bytes, when splitting by size
+        #[arg(long, required_if_eq("by", "size"))]
+        max_size: Option<u64>,
+    },
+    /// Move a song within `manifest`, printing the new index and ID of each moved song
+    #[command(group(
+        ArgGroup::new("destination")
+            .required(true)
+            .args(["to", "before", "after", "swap"])
+    ))]
+    Move {
+        /// The `manifest` to modify
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The index or ID of the song to move
+        #[arg(long)]
+        from: String,
+
+        /// The index to move the song to
+        #[arg(long)]
+        to: Option<usize>,
+
+        /// Move the song right before this song, given by index or ID
+        #[arg(long)]
+        before: Option<String>,
+
+        /// Move the song right after this song, given by index or ID
+        #[arg(long)]
+        after: Option<String>,
+
+        /// Swap the song with this song, given by index or ID
+        #[arg(long)]
+        swap: Option<String>,
+    },
+    /// Combine the manifests `inputs` into one, printing the index and ID of each song
+    ///
+    /// Songs keep their URLs and, unless they have one, get the title of their manifest as
+    /// album, so that each input becomes a section of the page. Settings like the theme are
+    /// taken from the first input.
+    Merge {
+        /// The manifests to combine, in order
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// The manifest to write, whose directory must contain the local files of the inputs
+        #[arg(long = "output", short = 'o', value_name = "OUTPUT")]
+        merged: PathBuf,
+
+        /// What to do with songs in several inputs: `error`, `skip` them, or keep the
+        /// `prefer-first` copy
+        #[arg(long, default_value = "error")]
+        duplicates: merge::Duplicates,
+
+        /// The title of the combined manifest, instead of that of the first input
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Find songs whose name, path, album or tags contain `query`, ignoring case, printing
+    /// their index, ID, name and URL
+    Search {
+        /// The `manifest` to search
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The text to search for
+        query: String,
+
+        /// Treat `query` as a regular expression
+        #[arg(long)]
+        regex: bool,
+    },
+    /// Report how often tracks are played and downloaded
+    Stats {
+        #[command(subcommand)]
+        action: StatsAction,
+    },
+    /// Manage the named playlists of a manifest
+    Playlist {
+        #[command(subcommand)]
+        action: PlaylistAction,
+    },
+    /// Sort the songs in `manifest`, printing the new index and ID of each moved song
+    Sort {
+        /// The `manifest` to sort
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The song field to sort by
+        #[arg(long, value_enum, default_value_t = SortKey::Name)]
+        by: SortKey,
+    },
+    /// Transcode the local audio files of `manifest` with ffmpeg into `output`, pointing the
+    /// manifest (or a fork of it) at the transcoded files
+    Transcode {
+        /// The `manifest` to transcode the songs of
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The codec to transcode to: `opus`, `mp3`, `aac` or `vorbis`
+        #[arg(long, default_value = "opus")]
+        codec: transcode::Codec,
+
+        /// The target bitrate, in ffmpeg notation
+        #[arg(long, default_value = "96k")]
+        bitrate: String,
+
+        /// The directory to write the transcoded files to, relative to the manifest directory
+        #[arg(long, default_value = "transcoded")]
+        output: PathBuf,
+
+        /// Write the updated manifest to this file next to `manifest` instead of modifying it
+        #[arg(long)]
+        fork: Option<PathBuf>,
+
+        /// Transcode again even if a transcoded file is newer than its source
+        #[arg(long)]
+        force: bool,
+    },
+    /// Download the remote songs of `manifest` into `dir`, resuming interrupted downloads
+    Download {
+        /// The `manifest` to download the songs of
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The directory to mirror the song paths into
+        #[arg(long, default_value = "downloads")]
+        dir: PathBuf,
+
+        /// How many songs to download at the same time
+        #[arg(long, default_value_t = 4)]
+        jobs: usize,
+
+        /// How long presigned URLs for `s3://` songs stay valid, in seconds
+        #[arg(long, default_value_t = 60 * 60)]
+        expiry: u64,
+
+        #[command(flatten)]
+        http: HttpArgs,
     },
     /// Format a `manifest`
+    ///
+    /// `manifest` may also be a URL, like for `add`.
     Format {
-        /// The `manifest` to format
-        #[arg(long, default_value = "tracks.json")]
+        /// The `manifest` to format, a file or URL
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// Reject fields unknown to this version, like misspelled ones, instead of dropping them
+        #[arg(long)]
+        strict_schema: bool,
+    },
+    /// Check that `manifest` can be read, its playlists refer to its songs and the URLs of all
+    /// songs can be built, without requesting them
+    Validate {
+        /// The `manifest` to validate
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// Reject fields unknown to this version, like misspelled ones
+        #[arg(long)]
+        strict_schema: bool,
+    },
+    /// Print the JSON Schema of manifests
+    ///
+    /// Editors and other tools can use it to complete and validate manifests.
+    Schema,
+    /// Upgrade `manifest` to the current layout version
+    ///
+    /// Older manifests are also read by all other commands, and upgraded when they are saved.
+    Migrate {
+        /// The `manifest` to upgrade
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+    },
+    /// Add the songs of the M3U, XSPF or CSV playlist `input` to `manifest`, creating it if
+    /// needed, printing their indices and IDs
+    ///
+    /// A new manifest gets the common base of the song URLs as prefix.
+    Import {
+        /// The playlist to import
+        input: PathBuf,
+
+        /// The format of `input`: `m3u`, `m3u8`, `xspf` or `csv`, by default from its extension
+        #[arg(long)]
+        format: Option<import::Format>,
+
+        /// The `manifest` to add the songs to
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The title of a new manifest, by default the playlist title or file name
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Fill in the canonical name, artist, album and year of songs from MusicBrainz, printing
+    /// the indices of the changed songs
+    ///
+    /// Each match is shown with the changes it makes, and applied once confirmed.
+    Identify {
+        /// The `manifest` to update
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// Only identify these songs, by ID or index; repeatable
+        #[arg(long = "song", value_name = "SONG")]
+        songs: Vec<String>,
+
+        /// Identify local files by their AcoustID fingerprint, computed with `fpcalc`, with the
+        /// application key in `$TRACKINATOR_ACOUSTID_KEY`; others are looked up by their tags
+        #[arg(long)]
+        fingerprint: bool,
+
+        /// Skip matches scoring below this, from 0 to 1
+        #[arg(long, default_value_t = 0.9)]
+        min_score: f64,
+
+        /// Apply all matches without asking
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Clean up the paths of `manifest`
+    ///
+    /// Paths are rewritten to `/` separators without `./` and `..` segments, so manifests
+    /// edited on Windows and elsewhere build the same URLs. Use `--dry-run` to see the changes.
+    Normalize {
+        /// The `manifest` to normalize
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// Make absolute paths relative to this directory, like `C:\Music`
+        #[arg(long)]
+        base: Option<PathBuf>,
+
+        /// Lowercase the extensions of song paths; rename the files to match
+        #[arg(long)]
+        lowercase_extensions: bool,
+    },
+    /// Encrypt a `manifest` at rest with the passphrase in `$TRACKINATOR_PASSPHRASE`
+    ///
+    /// Encrypted manifests are decrypted in memory by all commands and re-encrypted on save.
+    Encrypt {
+        /// The `manifest` to encrypt
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+    },
+    /// Decrypt an encrypted `manifest` back to plain JSON
+    Decrypt {
+        /// The `manifest` to decrypt
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+    },
+    /// Export the tracklist as data file of a Hugo or Zola site rooted at `output`
+    Export {
+        /// The JSON input file
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
         manifest: PathBuf,
+
+        /// The static site generator to export for
+        #[arg(long, value_enum)]
+        format: ExportFormat,
+
+        /// The root directory of the site, getting `data/tracks.json`
+        #[arg(long, default_value = ".")]
+        output: PathBuf,
+
+        /// Also write a `tracks` shortcode rendering the tracklist
+        #[arg(long)]
+        shortcode: bool,
+
+        /// How long presigned URLs for `s3://` songs stay valid, in seconds
+        #[arg(long, default_value_t = 24 * 60 * 60)]
+        expiry: u64,
     },
-    /// Generate a template manifest with default values
-    Template {
+    /// Upload the generated `output` (and optionally the local media) to a deploy target
+    Deploy {
+        /// The manifest of the deployed page
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The generated HTML file to upload
+        #[arg(long, env = "TRACKINATOR_OUTPUT", default_value = "index.html")]
+        output: PathBuf,
+
+        /// The target to upload to, overriding the manifest `deploy` target:
+        /// `s3://bucket/prefix`, `sftp://[user@]host/path`, or any rsync destination
+        #[arg(long)]
+        target: Option<deploy::Target>,
+
+        /// Also upload the local audio files referenced by the manifest
+        #[arg(long)]
+        media: bool,
+
+        /// Skip files uploaded unchanged by an interrupted deploy to the same target, as
+        /// recorded in `.trackinator-deploy.json` next to the manifest
+        #[arg(long)]
+        resume: bool,
+
+        /// Upload no faster than this rate, like `2mbps`
+        #[arg(long)]
+        bwlimit: Option<serve::Rate>,
+
+        /// Upload at most this many bytes, leaving the rest for a later `--resume`
+        #[arg(long)]
+        quota: Option<u64>,
+    },
+    /// Create a new manifest, empty or scaffolded from a preset
+    ///
+    /// Presets also write `trackinator.toml` and the example stylesheet `theme.css` next to
+    /// the manifest. Existing files are only replaced with `--force`.
+    #[command(alias = "template")]
+    Init {
         /// The `manifest` path
-        #[arg(long, default_value = "tracks.json")]
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
         manifest: PathBuf,
+
+        /// The URL prefix of the new manifest
+        #[arg(long, env = "TRACKINATOR_PREFIX", default_value = "")]
+        prefix: String,
+
+        /// The title of the new manifest
+        #[arg(long, default_value = "")]
+        title: String,
+
+        /// Scaffold the manifest for `band`, `podcast` or `archive`
+        #[arg(long)]
+        preset: Option<init::Preset>,
+
+        /// Ask for the title and prefix on the terminal
+        #[arg(short, long)]
+        interactive: bool,
+
+        /// Replace existing files
+        #[arg(long)]
+        force: bool,
+    },
+    /// Serve the page, an M3U playlist at `/playlist.m3u8` and streams at `/stream/<slug>`
+    ///
+    /// Local songs are streamed from disk, remote ones are redirected to.
+    Serve {
+        /// The `manifest` to serve
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The address to listen on, like `0.0.0.0:8080` to serve the LAN
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        address: SocketAddr,
+
+        /// Simulate a slow connection by sending streams at most at this rate, like `256kbps`
+        #[arg(long)]
+        throttle: Option<serve::Rate>,
+    },
+    /// Print a completion script for `shell` to stdout
+    Completions {
+        /// The shell to complete in
+        shell: Shell,
+    },
+    /// Print the man page to stdout, or write a page per subcommand into `directory`
+    Manpage {
+        /// The directory to write `trackinator.1`, `trackinator-generate.1` etc. to
+        #[arg(long)]
+        directory: Option<PathBuf>,
+    },
+    /// Measure generation and checking over a synthetic manifest, reporting throughput and memory
+    ///
+    /// Checks go to a local mock server, so no network is involved.
+    Benchmark {
+        /// The number of synthetic tracks
+        #[arg(long, default_value_t = 1000)]
+        tracks: usize,
+
+        /// How often to generate all formats
+        #[arg(long, default_value_t = 10)]
+        rounds: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// `data/tracks.json` and `layouts/shortcodes/tracks.html`
+    Hugo,
+    /// `data/tracks.json` and `templates/shortcodes/tracks.html`
+    Zola,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PlaylistAction {
+    /// Append a song to `playlist`, creating the playlist if needed
+    Add {
+        /// The `manifest` to modify
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The name of the playlist
+        #[arg(long)]
+        playlist: String,
+
+        /// The index or ID of the song to add
+        #[arg(long)]
+        song: String,
+    },
+    /// Remove a song from `playlist`, deleting the playlist once it is empty
+    Remove {
+        /// The `manifest` to modify
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The name of the playlist
+        #[arg(long)]
+        playlist: String,
+
+        /// The index or ID of the song to remove
+        #[arg(long)]
+        song: String,
     },
 }
 
+#[derive(Debug, Subcommand)]
+pub enum StatsAction {
+    /// Summarize the songs of `manifest`: their number, size and duration, counts by file
+    /// extension, album and tag, and the largest files
+    Summary {
+        /// The `manifest` to summarize
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// How many of the largest files to list
+        #[arg(long, default_value_t = 10)]
+        largest: usize,
+
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Count the requests for the tracks of `manifest` in web server access logs, in Common or
+    /// Combined Log Format, and print the plays and downloads per period and track
+    ///
+    /// Counts accumulate in `.trackinator-stats.json` next to the manifest. Requests already
+    /// imported from a log file of the same name are skipped.
+    ImportLogs {
+        /// The `manifest` whose tracks to count
+        #[arg(long, env = "TRACKINATOR_MANIFEST", default_value = "tracks.json")]
+        manifest: PathBuf,
+
+        /// The access logs to import
+        #[arg(required = true)]
+        logs: Vec<PathBuf>,
+
+        /// The period to report counts by: `day`, `week` or `month`
+        #[arg(long, default_value = "week")]
+        by: stats::Period,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PageOrder {
+    /// By song name
+    Name,
+    /// Newest first, by the time songs were added
+    Added,
+    /// Shortest first, by probed duration
+    Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// Sort by song name
+    Name,
+    /// Sort by song path
+    Path,
+    /// Sort by series, season and episode number
+    Episode,
+}
+
+/// How to request songs from their servers
+#[derive(Debug, Clone, Args)]
+pub struct HttpArgs {
+    /// An extra request header like `Cookie: session=1`, repeatable
+    #[arg(long = "header", value_name = "HEADER")]
+    headers: Vec<http::Header>,
+
+    /// The user agent to send instead of the default one
+    #[arg(long, env = "TRACKINATOR_USER_AGENT")]
+    user_agent: Option<String>,
+
+    /// Credentials for servers behind basic auth, as `user:password`
+    #[arg(long, env = "TRACKINATOR_BASIC_AUTH", hide_env_values = true)]
+    basic_auth: Option<http::BasicAuth>,
+}
+
+impl From<HttpArgs> for http::Options {
+    fn from(args: HttpArgs) -> Self {
+        Self {
+            headers: args.headers,
+            user_agent: args.user_agent,
+            basic_auth: args.basic_auth,
+        }
+    }
+}
+
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Arguments {
     #[command(subcommand)]
     action: Action,
+
+    /// Print a diff of manifest changes instead of writing them, and only show what
+    /// `deploy` would transfer
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Keep the previous version of a modified manifest as `<manifest>.bak`
+    #[arg(long, global = true)]
+    backup: bool,
+
+    /// Commit the manifest to its git repository after `add`, `remove`, `edit` and `sort`
+    #[arg(long, global = true)]
+    commit: bool,
+
+    /// Log more: `-v` for progress, `-vv` for debug details such as timing
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Log less: `-q` for errors only, `-qq` for nothing
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "verbose")]
+    quiet: u8,
+
+    /// The format of log messages on stderr
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Print a line per item instead of drawing progress bars, like for CI logs
+    #[arg(long, global = true)]
+    no_progress: bool,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Song {
-    name: String,
-    path: PathBuf,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line
+    Json,
 }
 
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
-pub struct Manifest {
-    title: String,
-    prefix: String,
-    songs: Vec<Song>,
+/// Log to stderr at warning level, adjusted by `verbose` and `quiet`
+fn init_logging(verbose: u8, quiet: u8, format: LogFormat) {
+    let level = match 2 + i16::from(verbose) - i16::from(quiet) {
+        ..=0 => return,
+        1 => Level::ERROR,
+        2 => Level::WARN,
+        3 => Level::INFO,
+        4 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
+
+/// How mutating commands write the manifest
+#[derive(Debug, Clone, Copy)]
+struct Writer {
+    dry_run: bool,
+    backup: bool,
+    commit: bool,
+}
+
+impl Writer {
+    /// Lock the manifest at `path` for a read-modify-write cycle, unless this is a `dry_run`
+    fn lock(&self, path: &Path) -> anyhow::Result<Option<lock::Lock>> {
+        if self.dry_run {
+            return Ok(None);
+        }
+        lock::exclusive(path).map(Some)
+    }
+
+    /// Save `manifest` to `path`, or with `dry_run` only print a diff of the changes
+    fn save(&self, manifest: &Manifest, path: &Path, storage: Storage) -> anyhow::Result<()> {
+        if self.dry_run {
+            print!("{}", manifest.diff(path)?);
+            return Ok(());
+        }
+        if self.backup {
+            atomic::backup(path).context("Failed to back up manifest")?;
+        }
+        manifest.save(path, storage)
+    }
+
+    /// Commit the manifest at `path` with the message `message`, if requested and not a
+    /// `dry_run`
+    fn commit(&self, path: &Path, message: impl FnOnce() -> String) -> anyhow::Result<()> {
+        if !self.commit || self.dry_run {
+            return Ok(());
+        }
+        if remote::url(path).is_some() {
+            tracing::warn!("Not committing remote manifest {}", path.display());
+            return Ok(());
+        }
+        git::commit(path, &message()).map(drop)
+    }
+
+    /// Read the manifest at `file`, a path or the URL of a remote manifest, rejecting unknown
+    /// fields if `strict`, and save it after `change`
+    ///
+    /// Local manifests are locked meanwhile, remote ones are only written back if they were not
+    /// changed meanwhile.
+    async fn modify<T>(
+        &self,
+        file: &Path,
+        strict: bool,
+        change: impl FnOnce(&mut Manifest) -> anyhow::Result<T>,
+    ) -> anyhow::Result<(Manifest, T)> {
+        if let Some(url) = remote::url(file) {
+            let (remote, mut manifest, storage) = remote::Remote::fetch(url, strict).await?;
+            let result = change(&mut manifest)?;
+            if self.dry_run {
+                print!("{}", remote.diff(&manifest)?);
+            } else {
+                if self.backup {
+                    remote.backup().await?;
+                }
+                remote.publish(&manifest, storage).await?;
+            }
+            return Ok((manifest, result));
+        }
+        let _lock = self.lock(file)?;
+        let (mut manifest, storage) = if strict {
+            Manifest::load_strict(file)?
+        } else {
+            Manifest::load(file)?
+        };
+        let result = change(&mut manifest)?;
+        self.save(&manifest, file, storage)?;
+        Ok((manifest, result))
+    }
+}
+
+/// `command` with the argument defaults replaced by those from `config`
+///
+/// Explicit arguments and environment variables still take precedence.
+fn with_defaults(mut command: Command, config: &Config) -> Command {
+    let defaults = [
+        (
+            "manifest",
+            config.manifest.as_ref().map(|p| p.as_os_str().to_owned()),
+        ),
+        (
+            "output",
+            config.output.as_ref().map(|p| p.as_os_str().to_owned()),
+        ),
+        ("prefix", config.prefix.clone().map(Into::into)),
+    ];
+    let names = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect::<Vec<_>>();
+    for name in names {
+        command = command.mut_subcommand(name, |sub| {
+            let mut sub = with_defaults(sub, config);
+            for (id, default) in &defaults {
+                let known = sub.get_arguments().any(|arg| arg.get_id() == id);
+                if let (true, Some(default)) = (known, default) {
+                    sub = sub.mut_arg(id, |arg| arg.default_value(default.clone()));
+                }
+            }
+            if let ("check", Some(verify)) = (sub.get_name(), config.check.verify) {
+                sub = sub.mut_arg("verify", |arg| arg.default_value(verify.to_string()));
+            }
+            sub
+        });
+    }
+    command
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let args = Arguments::parse();
+    let config = Config::discover()?;
+    let matches = with_defaults(Arguments::command(), &config).get_matches();
+    let args = Arguments::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    init_logging(args.verbose, args.quiet, args.log_format);
+    progress::init(!args.no_progress, args.quiet == 0);
+    let dry_run = args.dry_run;
+    let writer = Writer {
+        dry_run,
+        backup: args.backup,
+        commit: args.commit || config.commit,
+    };
     match args.action {
-        Action::Generate { manifest, output } => {
-            let reader = BufReader::new(File::open(manifest).context("Failed to open manifest")?);
-            let manifest: Manifest =
-                serde_json::from_reader(reader).context("Failed to read manifest")?;
-
-            let audio_tags = iter!(manifest.songs.iter().map(|s| laby::div!(
-                laby::h3!(s.name.clone()),
-                laby::audio!(
-                    class = "track",
-                    controls = "controls",
-                    source!(src = song_url(&manifest.prefix, s.path.to_str().unwrap_or_default()))
-                )
-            )));
-
-            let n = html!(
-                head!(title!(manifest.title),),
-                body!(class = "dark", audio_tags),
+        Action::Generate {
+            manifest: file,
+            output,
+            mut formats,
+            expiry,
+            probe,
+            jobs,
+            theme,
+            mut filters,
+            playlist,
+            only_tag,
+            only_album,
+            no_js,
+            sort,
+            highlight_recent,
+            download_links,
+            single_file,
+            embed_images,
+            skip_invalid,
+            pretty,
+        } => {
+            filters.extend(
+                only_tag
+                    .into_iter()
+                    .map(|tag| Filter::new(filter::Key::Tag, tag)),
             );
+            filters.extend(
+                only_album
+                    .into_iter()
+                    .map(|album| Filter::new(filter::Key::Album, album)),
+            );
+            let _lock = if probe { writer.lock(&file)? } else { None };
+            let (mut manifest, storage) = Manifest::load(&file)?;
+            let base = file.parent().unwrap_or(Path::new(""));
 
-            let result = render!(n);
-            std::fs::write(output, result)?;
-        }
-        Action::Check { manifest } => {
-            let reader = BufReader::new(File::open(manifest).context("Failed to open manifest")?);
-            let manifest: Manifest =
-                serde_json::from_reader(reader).context("Failed to read manifest")?;
-            let mut handles = Vec::new();
-            for song in manifest.songs {
-                let url = song_url(&manifest.prefix, song.path.to_str().unwrap_or_default());
-                let handle = tokio::spawn({
-                    println!("Checking {url}");
-                    lychee_lib::check(url)
-                });
-                handles.push(handle);
+            if probe {
+                let paths = manifest
+                    .songs
+                    .iter()
+                    .map(|song| base.join(&song.path))
+                    .collect();
+                let jobs = jobs.unwrap_or_else(probe::default_jobs);
+                let probed = probe::probe_all(paths, jobs, "Probing", |path| {
+                    Ok((probe::size(path)?, probe::duration(path)))
+                })
+                .await?;
+                for (song, probed) in manifest.songs.iter_mut().zip(probed) {
+                    let probed = probed.and_then(|(size, duration)| {
+                        song.size = Some(size);
+                        song.duration = Some(duration?);
+                        Ok(())
+                    });
+                    if let Err(e) = probed {
+                        tracing::warn!("Failed to probe {}: {e:#}", song.name);
+                    }
+                }
+                writer.save(&manifest, &file, storage)?;
             }
-            let responses = futures::future::try_join_all(handles)
-                .await
-                .context("Failed to join the check tasks")?
+
+            if let Some(playlist) = &playlist {
+                manifest.select_playlist(playlist)?;
+            }
+            let now = Utc::now();
+            let all = std::mem::take(&mut manifest.songs);
+            manifest.songs = all
                 .into_iter()
-                .collect::<Result<Vec<Response>, _>>()
-                .context("Resource unreachable")?;
+                .filter(|song| song.is_published(now))
+                .filter(|song| filter::matches_all(&filters, &manifest, song))
+                .collect();
+            if theme.is_some() {
+                manifest.theme = theme;
+            }
+            match sort {
+                Some(PageOrder::Name) => manifest.songs.sort_by(|a, b| a.name.cmp(&b.name)),
+                // Newest first, songs without date last
+                Some(PageOrder::Added) => manifest
+                    .songs
+                    .sort_by_key(|song| std::cmp::Reverse(song.added)),
+                Some(PageOrder::Duration) => {
+                    manifest
+                        .songs
+                        .sort_by(|a, b| match (a.duration, b.duration) {
+                            (Some(a), Some(b)) => a.total_cmp(&b),
+                            (a, b) => b.is_some().cmp(&a.is_some()),
+                        })
+                }
+                None => {}
+            }
+
+            if single_file {
+                // Styles and scripts are inlined already, only other outputs are linked
+                formats = vec!["html".to_string()];
+            }
+            let pages = formats
+                .iter()
+                .any(|format| format == "html" || format == "seasons");
+            let invalid = error::invalid_tracks(&manifest, base, pages);
+            if !invalid.is_empty() && !skip_invalid {
+                let errors = invalid.iter().map(|error| format!("\n  {error}"));
+                anyhow::bail!(
+                    "{} tracks cannot be published, leave them out with --skip-invalid:{}",
+                    invalid.len(),
+                    errors.collect::<String>()
+                );
+            }
+            for error in invalid.iter().rev() {
+                manifest.songs.remove(error.index);
+            }
+            let urls = published_urls(&manifest, expiry)?;
+            let page = output.file_name().context("Invalid output file name")?;
+            let directory = output.parent().unwrap_or(Path::new(""));
+            let mut generators = generate::Generators::builtin(page, base);
+            let archives = formats.iter().any(|format| format == "zip");
+            let recent = highlight_recent.map(|days| now - chrono::Duration::days(days.into()));
+            let mut html = generate::Html::new(page)
+                .base(base)
+                .archives(archives)
+                .no_js(no_js)
+                .download_links(download_links)
+                .embed_images(embed_images)
+                .pretty(pretty);
+            if let Some(since) = recent {
+                html = html.highlight_since(since);
+            }
+            generators.register(generate::Seasons::new(base).page(html.clone()));
+            generators.register(html);
+            for artifact in generators.generate(&formats, &manifest, &urls)? {
+                let path = directory.join(&artifact.path);
+                artifact.contents.write(&path)?;
+                tracing::info!("Wrote {}", path.display());
+            }
+            if !invalid.is_empty() {
+                tracing::warn!("Skipped {} invalid tracks:", invalid.len());
+                for error in &invalid {
+                    tracing::warn!("  {error}");
+                }
+            }
+        }
+        Action::Check {
+            manifest: file,
+            verify,
+            fail_fast,
+            max_failures,
+            max_age,
+            force,
+            watch,
+            method,
+            http,
+        } => {
+            let http = http::Options::from(http);
+            let max_age = max_age.filter(|_| !force).map(chrono::Duration::seconds);
+            let max_failures = if fail_fast { Some(1) } else { max_failures };
+            let Some(interval) = watch else {
+                let (summary, report) =
+                    check_manifest(&file, verify, method, &http, max_failures, max_age).await?;
+                print!("{report}");
+                println!("{summary}");
+                let code = summary.exit_code();
+                if code != 0 {
+                    let notification = outage(&file, &summary, &report);
+                    notify::notify_all(&config.notify, &notification).await;
+                    process::exit(code);
+                }
+                return Ok(());
+            };
+            let mut failing = false;
+            loop {
+                match check_manifest(&file, verify, method, &http, max_failures, max_age).await {
+                    Ok((summary, report)) => {
+                        print!("{report}");
+                        println!("{summary}");
+                        let now_failing = summary.exit_code() != 0;
+                        if now_failing && !failing {
+                            let notification = outage(&file, &summary, &report);
+                            notify::notify_all(&config.notify, &notification).await;
+                        } else if failing && !now_failing {
+                            let notification = notify::Notification {
+                                subject: format!("All tracks of {} are back", file.display()),
+                                body: summary.to_string(),
+                            };
+                            notify::notify_all(&config.notify, &notification).await;
+                        }
+                        failing = now_failing;
+                    }
+                    Err(e) => tracing::error!("Failed to check {}: {e:#}", file.display()),
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+            }
+        }
+        Action::CheckOutput { output, base_url } => {
+            let responses = check::check_page(&output, base_url).await?;
+            let mut summary = check::Summary::default();
+            let mut report = check::HostReport::default();
+            for response in &responses {
+                let outcome = check::Outcome::of(response.status());
+                summary.add(outcome);
+                report.add(response);
+                if outcome != check::Outcome::Ok {
+                    tracing::info!(
+                        "not reachable {} ({})",
+                        check::redact(&response.1.uri),
+                        response.status()
+                    )
+                }
+            }
+            print!("{report}");
+            println!("{summary}");
+            let code = summary.exit_code();
+            if code != 0 {
+                process::exit(code);
+            }
+        }
+        Action::Hash {
+            manifest: file,
+            local,
+            jobs,
+        } => {
+            let _lock = writer.lock(&file)?;
+            let (mut manifest, storage) = Manifest::load(&file)?;
+            let local = local.unwrap_or_else(|| file.parent().unwrap_or(Path::new("")).into());
+            let paths = manifest
+                .songs
+                .iter()
+                .map(|song| local.join(&song.path))
+                .collect();
+            let jobs = jobs.unwrap_or_else(probe::default_jobs);
+            let hashed = probe::probe_all(paths, jobs, "Hashing", |path| {
+                Ok((hash::sha256_file(path)?, probe::size(path)?))
+            })
+            .await?;
+            for (song, hashed) in manifest.songs.iter_mut().zip(hashed) {
+                let (hash, size) = hashed?;
+                song.hash = Some(hash);
+                song.size = Some(size);
+            }
+            writer.save(&manifest, &file, storage)?;
+        }
+        Action::CompareAudio {
+            manifest: file,
+            local,
+            hash,
+        } => {
+            let manifest = Manifest::read(&file)?;
+            let local = local.unwrap_or_else(|| file.parent().unwrap_or(Path::new("")).into());
+            let client = &reqwest::Client::new();
+            let mut credentials = None;
+            let mut comparisons = Vec::new();
+            for song in &manifest.songs {
+                let mut url = song_url(&manifest, song)?;
+                if s3::is_s3(&url) {
+                    if credentials.is_none() {
+                        credentials = Some(s3::Credentials::from_env()?);
+                    }
+                    let method = if hash { "GET" } else { "HEAD" };
+                    url = s3::presign(&url, method, 60 * 60, credentials.as_ref().unwrap())?;
+                } else if !matches!(Url::parse(&url), Ok(url) if url.scheme().starts_with("http")) {
+                    // Without a hosted copy there is nothing to compare against
+                    continue;
+                }
+                let path = local.join(&song.path);
+                comparisons.push(async move {
+                    let drift = compare::compare(client, &path, &url, hash).await;
+                    (song, drift)
+                });
+            }
             let mut error = false;
-            for response in responses {
-                if !response.status().is_success() {
-                    error = true;
-                    eprintln!("not reachable {}", response.0)
+            for (song, drift) in futures::future::join_all(comparisons).await {
+                match drift {
+                    Ok(None) => {}
+                    Ok(Some(drift)) => {
+                        error = true;
+                        println!("{}: {drift}", song.name);
+                    }
+                    Err(e) => {
+                        error = true;
+                        tracing::warn!("{}: {e:#}", song.name);
+                    }
                 }
             }
             if error {
@@ -132,35 +1342,986 @@ async fn main() -> anyhow::Result<()> {
             manifest: file,
             name,
             path,
+            album,
+            artist,
+            series,
+            season,
+            episode,
+            tags,
+            mime,
+            prefix,
+            lyrics_lrc,
+            unpublish_at,
+        } => {
+            // Fail now rather than on every later load of the manifest
+            let path = normalize_path(&path)?;
+            let lyrics_lrc = lyrics_lrc.as_deref().map(normalize_path).transpose()?;
+            let mut new_song = Song {
+                name,
+                path,
+                album,
+                artist,
+                series,
+                season,
+                episode,
+                mime,
+                prefix,
+                lyrics_lrc,
+                unpublish_at,
+                added: Some(Utc::now()),
+                ..Default::default()
+            };
+            new_song.add_tags(tags);
+            let (manifest, index) = writer
+                .modify(&file, false, |manifest| {
+                    manifest.songs.push(new_song);
+                    Ok(manifest.songs.len() - 1)
+                })
+                .await?;
+            writer.commit(&file, || {
+                format!("Add track: {}", manifest.songs[index].name)
+            })?;
+            print_indices(&manifest, &[index]);
+        }
+        Action::Remove {
+            manifest: file,
+            song,
+        } => {
+            let _lock = writer.lock(&file)?;
+            let (mut manifest, storage) = Manifest::load(&file)?;
+            let index = manifest.find(&song)?;
+            let before = manifest.slugs();
+            let removed = manifest.songs.remove(index);
+            manifest.update_playlists(&before, |old| match old.cmp(&index) {
+                std::cmp::Ordering::Less => Some(old),
+                std::cmp::Ordering::Equal => None,
+                std::cmp::Ordering::Greater => Some(old - 1),
+            });
+            writer.save(&manifest, &file, storage)?;
+            writer.commit(&file, || format!("Remove track: {}", removed.name))?;
+            print_indices(
+                &manifest,
+                &(index..manifest.songs.len()).collect::<Vec<_>>(),
+            );
+        }
+        Action::Edit {
+            manifest: file,
+            song,
+            name,
+            album,
+            artist,
+            add_tags,
+            remove_tags,
+        } => {
+            let _lock = writer.lock(&file)?;
+            let (mut manifest, storage) = Manifest::load(&file)?;
+            let index = manifest.find(&song)?;
+            let before = manifest.slugs();
+            let song = &mut manifest.songs[index];
+            if let Some(name) = name {
+                song.name = name;
+            }
+            if album.is_some() {
+                song.album = album;
+            }
+            if artist.is_some() {
+                song.artist = artist;
+            }
+            song.tags.retain(|tag| !remove_tags.contains(tag));
+            song.add_tags(add_tags);
+            manifest.update_playlists(&before, Some);
+            writer.save(&manifest, &file, storage)?;
+            writer.commit(&file, || {
+                format!("Edit track: {}", manifest.songs[index].name)
+            })?;
+            print_indices(&manifest, &[index]);
+        }
+        Action::List {
+            manifest: file,
+            filters,
+        } => {
+            let manifest = Manifest::read(&file)?;
+            let ids = manifest.slugs();
+            for (index, song) in manifest.songs.iter().enumerate() {
+                if filter::matches_all(&filters, &manifest, song) {
+                    println!(
+                        "{index}\t{}\t{}\t{}",
+                        ids[index],
+                        song.name,
+                        song.tags.join(",")
+                    );
+                }
+            }
+        }
+        Action::Merge {
+            inputs,
+            merged: file,
+            duplicates,
+            title,
+        } => {
+            let inputs = inputs
+                .into_iter()
+                .map(|input| Ok((input.clone(), Manifest::read(&input)?)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let directory = file.parent().unwrap_or(Path::new(""));
+            let mut manifest = merge::merge(inputs, directory, duplicates)?;
+            if let Some(title) = title {
+                manifest.title = title;
+            }
+            let _lock = writer.lock(&file)?;
+            writer.save(&manifest, &file, Storage::Plain)?;
+            print_indices(&manifest, &(0..manifest.songs.len()).collect::<Vec<_>>());
+        }
+        Action::Changelog {
+            since,
+            manifest: file,
+        } => {
+            let new = Manifest::read(&file)?;
+            let (old, _) = if Path::new(&since).is_file() {
+                Manifest::load(Path::new(&since))?
+            } else {
+                changelog::at_revision(&file, &since)?
+            };
+            for change in changelog::changes(&old, &new) {
+                println!("{change}");
+            }
+        }
+        Action::Lint {
+            mut manifests,
+            manifest,
+        } => {
+            if manifests.is_empty() {
+                manifests = config.workspace.clone();
+            }
+            if manifests.is_empty() {
+                manifests.push(manifest);
+            }
+            let manifests = manifests
+                .into_iter()
+                .map(|path| {
+                    let manifest = Manifest::read(&path)
+                        .with_context(|| format!("Failed to lint {}", path.display()))?;
+                    Ok((path, manifest))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let duplicates = lint::duplicates(&manifests)?;
+            for duplicate in &duplicates {
+                println!("{duplicate}");
+            }
+            if !duplicates.is_empty() {
+                process::exit(1);
+            }
+        }
+        Action::Split {
+            manifest: file,
+            by,
+            max_size,
+        } => {
+            let (manifest, storage) = Manifest::load(&file)?;
+            let base = file.parent().unwrap_or(Path::new(""));
+            let stem = file
+                .file_stem()
+                .context("Invalid manifest file name")?
+                .to_string_lossy();
+            for part in split::split(&manifest, by, max_size, base)? {
+                let path = base.join(format!("{stem}-{}.json", part.name));
+                let _lock = writer.lock(&path)?;
+                writer.save(&part.manifest, &path, storage)?;
+                println!("{}", path.display());
+            }
+        }
+        Action::Move {
+            manifest: file,
+            from,
+            to,
+            before,
+            after,
+            swap,
+        } => {
+            let _lock = writer.lock(&file)?;
+            let (mut manifest, storage) = Manifest::load(&file)?;
+            let from = manifest.find(&from)?;
+            let mut order = (0..manifest.songs.len()).collect::<Vec<_>>();
+            let (first, last) = if let Some(other) = swap {
+                let other = manifest.find(&other)?;
+                order.swap(from, other);
+                (from.min(other), from.max(other))
+            } else {
+                let to = match (to, before, after) {
+                    (Some(to), _, _) => {
+                        if to >= manifest.songs.len() {
+                            anyhow::bail!(
+                                "Index {to} is out of bounds for {} songs",
+                                manifest.songs.len()
+                            );
+                        }
+                        to
+                    }
+                    (_, Some(other), _) => {
+                        let other = manifest.find(&other)?;
+                        if other == from {
+                            anyhow::bail!("Cannot move a song before itself");
+                        }
+                        if from < other {
+                            other - 1
+                        } else {
+                            other
+                        }
+                    }
+                    (_, _, Some(other)) => {
+                        let other = manifest.find(&other)?;
+                        if other == from {
+                            anyhow::bail!("Cannot move a song after itself");
+                        }
+                        if from < other {
+                            other
+                        } else {
+                            other + 1
+                        }
+                    }
+                    (None, None, None) => unreachable!("clap requires a destination"),
+                };
+                order.remove(from);
+                order.insert(to, from);
+                (from.min(to), from.max(to))
+            };
+            manifest.reorder(&order);
+            writer.save(&manifest, &file, storage)?;
+            if first != last {
+                print_indices(&manifest, &(first..=last).collect::<Vec<_>>());
+            }
+        }
+        Action::Search {
+            manifest: file,
+            query,
+            regex,
+        } => {
+            let pattern = if regex { query } else { regex::escape(&query) };
+            let pattern = regex::RegexBuilder::new(&pattern)
+                .case_insensitive(true)
+                .build()
+                .with_context(|| format!("Invalid search pattern {pattern}"))?;
+            let manifest = Manifest::read(&file)?;
+            let ids = manifest.slugs();
+            for (index, song) in manifest.songs.iter().enumerate() {
+                let path = song.path.to_string_lossy();
+                let fields = [Some(song.name.as_str()), Some(&path), song.album.as_deref()];
+                let found = fields
+                    .into_iter()
+                    .flatten()
+                    .any(|field| pattern.is_match(field))
+                    || song.tags.iter().any(|tag| pattern.is_match(tag));
+                if found {
+                    let url = song_url(&manifest, song)?;
+                    println!("{index}\t{}\t{}\t{url}", ids[index], song.name);
+                }
+            }
+        }
+        Action::Stats { action } => match action {
+            StatsAction::Summary {
+                manifest: file,
+                largest,
+                json,
+            } => {
+                let manifest = Manifest::read(&file)?;
+                let base = file.parent().unwrap_or(Path::new(""));
+                let summary = stats::Summary::new(&manifest, base, largest);
+                if json {
+                    let json = serde_json::to_string_pretty(&summary)
+                        .context("Failed to serialize summary")?;
+                    println!("{json}");
+                } else {
+                    print!("{summary}");
+                }
+            }
+            StatsAction::ImportLogs {
+                manifest: file,
+                logs,
+                by,
+            } => {
+                let manifest = Manifest::read(&file)?;
+                let path = file
+                    .parent()
+                    .unwrap_or(Path::new(""))
+                    .join(stats::STATS_FILE);
+                let _lock = writer.lock(&path)?;
+                let mut stats = stats::Stats::load(&path)?;
+                for log in logs {
+                    let contents = std::fs::read_to_string(&log)
+                        .with_context(|| format!("Failed to read log {}", log.display()))?;
+                    let source = log
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    let imported = stats.import(&manifest, &source, &contents);
+                    tracing::info!(
+                        "Imported {} requests from {}, skipped {} seen and {} invalid lines",
+                        imported.requests,
+                        log.display(),
+                        imported.seen,
+                        imported.invalid
+                    );
+                }
+                if !dry_run {
+                    stats.save(&path)?;
+                }
+                println!("period\ttrack\tplays\tdownloads");
+                for (period, id, counts) in stats.report(&manifest, by) {
+                    println!("{period}\t{id}\t{}\t{}", counts.plays, counts.downloads);
+                }
+            }
+        },
+        Action::Playlist { action } => match action {
+            PlaylistAction::Add {
+                manifest: file,
+                playlist,
+                song,
+            } => {
+                let _lock = writer.lock(&file)?;
+                let (mut manifest, storage) = Manifest::load(&file)?;
+                let index = manifest.find(&song)?;
+                let id = manifest.slugs().swap_remove(index);
+                manifest.playlists.entry(playlist).or_default().push(id);
+                writer.save(&manifest, &file, storage)?;
+                print_indices(&manifest, &[index]);
+            }
+            PlaylistAction::Remove {
+                manifest: file,
+                playlist,
+                song,
+            } => {
+                let _lock = writer.lock(&file)?;
+                let (mut manifest, storage) = Manifest::load(&file)?;
+                let index = manifest.find(&song)?;
+                let id = manifest.slugs().swap_remove(index);
+                let ids = manifest
+                    .playlists
+                    .get_mut(&playlist)
+                    .with_context(|| format!("No playlist {playlist}"))?;
+                let position = ids
+                    .iter()
+                    .position(|other| *other == id)
+                    .with_context(|| format!("Song {id} is not in playlist {playlist}"))?;
+                ids.remove(position);
+                if ids.is_empty() {
+                    manifest.playlists.remove(&playlist);
+                }
+                writer.save(&manifest, &file, storage)?;
+                print_indices(&manifest, &[index]);
+            }
+        },
+        Action::Sort { manifest: file, by } => {
+            let _lock = writer.lock(&file)?;
+            let (mut manifest, storage) = Manifest::load(&file)?;
+            let before = manifest.slugs();
+            // Sort the indices, to update the IDs in playlists by where each song went
+            let mut order = (0..manifest.songs.len()).collect::<Vec<_>>();
+            let songs = &manifest.songs;
+            match by {
+                SortKey::Name => order.sort_by(|&a, &b| songs[a].name.cmp(&songs[b].name)),
+                SortKey::Path => order.sort_by(|&a, &b| songs[a].path.cmp(&songs[b].path)),
+                SortKey::Episode => order.sort_by(|&a, &b| songs[a].cmp_episodes(&songs[b])),
+            }
+            manifest.reorder(&order);
+            writer.save(&manifest, &file, storage)?;
+            writer.commit(&file, || {
+                let by = by.to_possible_value().expect("no skipped sort keys");
+                format!("Sort tracks by {}", by.get_name())
+            })?;
+            let moved = manifest
+                .slugs()
+                .iter()
+                .enumerate()
+                .filter(|(index, id)| before[*index] != **id)
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+            print_indices(&manifest, &moved);
+        }
+        Action::Transcode {
+            manifest: file,
+            codec,
+            bitrate,
+            output,
+            fork,
+            force,
+        } => {
+            let _lock = writer.lock(&file)?;
+            let (mut manifest, storage) = Manifest::load(&file)?;
+            let base = file.parent().unwrap_or(Path::new(""));
+            // Songs below a remote prefix have no local source file, and keep their entries
+            let mut pending = Vec::new();
+            for song in &manifest.songs {
+                let remote = Url::parse(&plain_song_url(&manifest, song)?).is_ok();
+                if remote {
+                    tracing::info!("Skipping remote song {}", song.name);
+                }
+                pending.push(!remote && !song.path.starts_with(&output));
+            }
+            let songs = pending.iter().filter(|pending| **pending).count();
+            let progress = (!dry_run)
+                .then(|| progress::Progress::new(songs, "Transcoding", "songs"))
+                .transpose()?;
+            for (song, pending) in manifest.songs.iter_mut().zip(pending) {
+                if !pending {
+                    continue;
+                }
+                let target = output.join(song.path.with_extension(codec.extension()));
+                let (source_file, target_file) = (base.join(&song.path), base.join(&target));
+                if let Some(progress) = &progress {
+                    if force || !transcode::is_up_to_date(&source_file, &target_file) {
+                        progress.start(source_file.display());
+                        tracing::info!("Transcoding {}", source_file.display());
+                        transcode::transcode(&source_file, &target_file, codec, &bitrate)?;
+                        progress.add_bytes(probe::size(&source_file).unwrap_or(0));
+                        progress.done(target_file.display());
+                    } else {
+                        progress.done(format!("{} is up to date", target_file.display()));
+                    }
+                } else {
+                    println!("{} -> {}", source_file.display(), target_file.display());
+                }
+                song.path = target;
+                song.mime = None;
+                song.hash = None;
+                song.size = probe::size(&target_file).ok();
+            }
+            if let Some(progress) = progress {
+                progress.finish();
+            }
+            let file = match fork {
+                Some(fork) => base.join(fork),
+                None => file,
+            };
+            writer.save(&manifest, &file, storage)?;
+        }
+        Action::Download {
+            manifest: file,
+            dir,
+            jobs,
+            expiry,
+            http,
+        } => {
+            let manifest = Manifest::read(&file)?;
+            let urls = published_urls(&manifest, expiry)?;
+            let downloads = manifest
+                .songs
+                .iter()
+                .zip(urls)
+                .filter(|(song, url)| {
+                    let remote = url.starts_with("http://") || url.starts_with("https://");
+                    if !remote {
+                        tracing::info!("Skipping local song {}", song.name);
+                    }
+                    remote
+                })
+                .map(|(song, url)| download::Download {
+                    url,
+                    path: dir.join(&song.path),
+                })
+                .collect::<Vec<_>>();
+            if dry_run {
+                for download in &downloads {
+                    println!("{} -> {}", download.url, download.path.display());
+                }
+            } else {
+                let client = http::Options::from(http).client()?;
+                download::download_all(downloads, &client, jobs).await?;
+            }
+        }
+        Action::Format {
+            manifest: file,
+            strict_schema,
+        } => {
+            writer.modify(&file, strict_schema, |_| Ok(())).await?;
+        }
+        Action::Validate {
+            manifest: file,
+            strict_schema,
+        } => {
+            let (manifest, _) = if strict_schema {
+                Manifest::load_strict(&file)?
+            } else {
+                Manifest::load(&file)?
+            };
+            for name in manifest.playlists.keys() {
+                manifest.clone().select_playlist(name)?;
+            }
+            for song in &manifest.songs {
+                song_url(&manifest, song)?;
+            }
+            tracing::info!(
+                "{} is valid, with {} songs",
+                file.display(),
+                manifest.songs.len()
+            );
+        }
+        Action::Schema => {
+            let schema = serde_json::to_string_pretty(&Manifest::json_schema())
+                .context("Failed to serialize schema")?;
+            println!("{schema}");
+        }
+        Action::Migrate { manifest: file } => {
+            let _lock = writer.lock(&file)?;
+            let (manifest, storage) = Manifest::load(&file)?;
+            writer.save(&manifest, &file, storage)?;
+        }
+        Action::Import {
+            input,
+            format,
+            manifest: file,
+            title,
+        } => {
+            let format = match format {
+                Some(format) => format,
+                None => import::Format::of(&input)?,
+            };
+            let bytes = std::fs::read(&input)
+                .with_context(|| format!("Failed to read {}", input.display()))?;
+            // Plain M3U files are often in a legacy encoding
+            let playlist = import::parse(&String::from_utf8_lossy(&bytes), format)?;
+            let _lock = writer.lock(&file)?;
+            let (mut manifest, storage) = if file.exists() {
+                Manifest::load(&file)?
+            } else {
+                let title = title.or_else(|| playlist.title.clone()).unwrap_or_else(|| {
+                    let stem = input.file_stem().unwrap_or_default();
+                    stem.to_string_lossy().into_owned()
+                });
+                let manifest = Manifest {
+                    title,
+                    ..Default::default()
+                };
+                (manifest, Storage::Plain)
+            };
+            let directory = file.parent().unwrap_or(Path::new(""));
+            let added = import::import(&mut manifest, playlist, &input, directory)?;
+            writer.save(&manifest, &file, storage)?;
+            print_indices(&manifest, &added);
+        }
+        Action::Identify {
+            manifest: file,
+            songs,
+            fingerprint,
+            min_score,
+            yes,
+        } => {
+            let _lock = writer.lock(&file)?;
+            let (mut manifest, storage) = Manifest::load(&file)?;
+            let base = file.parent().unwrap_or(Path::new(""));
+            let indices = if songs.is_empty() {
+                (0..manifest.songs.len()).collect()
+            } else {
+                songs
+                    .iter()
+                    .map(|song| manifest.find(song))
+                    .collect::<anyhow::Result<Vec<_>>>()?
+            };
+            let key = std::env::var(identify::ACOUSTID_KEY_VAR).ok();
+            let mut client = identify::Client::new(key)?;
+            let mut changed = Vec::new();
+            for index in indices {
+                let song = &manifest.songs[index];
+                let path = base.join(&song.path);
+                let found = if fingerprint && path.is_file() {
+                    let fingerprint = identify::fingerprint(&path)?;
+                    client
+                        .by_fingerprint(&fingerprint, song.album.as_deref())
+                        .await?
+                } else {
+                    client.by_tags(song, manifest.artist.as_deref()).await?
+                };
+                let Some(mut found) = found.filter(|found| found.score >= min_score) else {
+                    tracing::info!("No match for {}", song.name);
+                    continue;
+                };
+                // Track artists are only set where they differ from the album artist
+                if song.artist.is_none() && found.artist == manifest.artist {
+                    found.artist = None;
+                }
+                let changes = found.changes(song);
+                if changes.is_empty() {
+                    continue;
+                }
+                println!("{}: {found}", song.name);
+                for (field, old, new) in changes {
+                    println!("  {field}: {old} -> {new}");
+                }
+                if !yes {
+                    match prompt("Apply? yes, no or quit", "yes")?.as_str() {
+                        "y" | "yes" => {}
+                        "q" | "quit" => break,
+                        _ => continue,
+                    }
+                }
+                let before = manifest.slugs();
+                found.apply(&mut manifest.songs[index]);
+                manifest.update_playlists(&before, Some);
+                changed.push(index);
+            }
+            writer.save(&manifest, &file, storage)?;
+            print_indices(&manifest, &changed);
+        }
+        Action::Normalize {
+            manifest: file,
+            base,
+            lowercase_extensions,
+        } => {
+            let options = normalize::Options {
+                base,
+                lowercase_extensions,
+            };
+            writer
+                .modify(&file, false, |manifest| {
+                    normalize::normalize(manifest, &options)
+                })
+                .await?;
+        }
+        Action::Encrypt { manifest: file } => {
+            let _lock = writer.lock(&file)?;
+            let manifest = Manifest::read(&file)?;
+            writer.save(&manifest, &file, Storage::Encrypted)?;
+        }
+        Action::Decrypt { manifest: file } => {
+            let _lock = writer.lock(&file)?;
+            let manifest = Manifest::read(&file)?;
+            writer.save(&manifest, &file, Storage::Plain)?;
+        }
+        Action::Export {
+            manifest: file,
+            format,
+            output,
+            shortcode,
+            expiry,
         } => {
-            let reader = BufReader::new(File::open(&file).context("Failed to open manifest")?);
-            let mut manifest: Manifest =
-                serde_json::from_reader(reader).context("Failed to read manifest")?;
-            let new_song = Song { name, path };
-            manifest.songs.push(new_song);
-            let manifest =
-                serde_json::to_string_pretty(&manifest).context("Failed to serialize manifest")?;
-            std::fs::write(&file, manifest).context("Failed to write manifest")?;
-        }
-        Action::Format { manifest: file } => {
-            let reader = BufReader::new(File::open(&file).context("Failed to open manifest")?);
-            let manifest: Manifest =
-                serde_json::from_reader(reader).context("Failed to read manifest")?;
-            let manifest =
-                serde_json::to_string_pretty(&manifest).context("Failed to serialize manifest")?;
-            std::fs::write(&file, manifest).context("Failed to write manifest")?;
-        }
-        Action::Template { manifest } => {
-            let empty = Manifest::default();
-            let template = serde_json::to_string_pretty(&empty)
+            let mut manifest = Manifest::read(&file)?;
+            let now = Utc::now();
+            manifest.songs.retain(|song| song.is_published(now));
+            let urls = published_urls(&manifest, expiry)?;
+            let kind = match format {
+                ExportFormat::Hugo => generate::SiteKind::Hugo,
+                ExportFormat::Zola => generate::SiteKind::Zola,
+            };
+            let site = generate::Site::new(kind).shortcode(shortcode);
+            for artifact in generate::Generator::generate(&site, &manifest, &urls)? {
+                let path = output.join(&artifact.path);
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)
+                        .with_context(|| format!("Failed to create {}", parent.display()))?;
+                }
+                artifact.contents.write(&path)?;
+                tracing::info!("Wrote {}", path.display());
+            }
+        }
+        Action::Deploy {
+            manifest: file,
+            output,
+            target,
+            media,
+            resume,
+            bwlimit,
+            quota,
+        } => {
+            let manifest = Manifest::read(&file)?;
+            let target = match (target, &manifest.deploy) {
+                (Some(target), _) => target,
+                (None, Some(target)) => target.parse()?,
+                (None, None) => anyhow::bail!("No deploy target given in manifest or arguments"),
+            };
+            let name = output
+                .file_name()
+                .and_then(|name| name.to_str())
+                .context("Invalid output file name")?;
+            let mut uploads = vec![deploy::Upload {
+                base: output.parent().unwrap_or(Path::new("")).to_path_buf(),
+                remote: name.to_string(),
+            }];
+            if media {
+                let base = file.parent().unwrap_or(Path::new(""));
+                for song in &manifest.songs {
+                    let url = song_url(&manifest, song)?;
+                    if Url::parse(&url).is_ok() {
+                        continue;
+                    }
+                    let remote = decode_path(&url)?
+                        .to_str()
+                        .with_context(|| format!("Path of {} is not valid UTF-8", song.name))?
+                        .to_string();
+                    uploads.push(deploy::Upload {
+                        base: base.to_path_buf(),
+                        remote,
+                    });
+                }
+            }
+            let checkpoint_path = file
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join(deploy::CHECKPOINT_FILE);
+            let mut checkpoint = if resume {
+                deploy::Checkpoint::load(checkpoint_path, &target)?
+            } else {
+                deploy::Checkpoint::new(checkpoint_path, &target)
+            };
+            let options = deploy::Options {
+                dry_run,
+                bwlimit,
+                quota,
+            };
+            let deployed = deploy::deploy(&target, &uploads, options, &mut checkpoint)?;
+            if deployed.skipped > 0 {
+                tracing::info!("Skipped {} files uploaded before", deployed.skipped);
+            }
+            if deployed.remaining > 0 {
+                println!(
+                    "Quota reached, {} files remain: continue with --resume",
+                    deployed.remaining
+                );
+            }
+            if !dry_run && deployed.remaining == 0 {
+                let title = match manifest.title.as_str() {
+                    "" => file.display().to_string(),
+                    title => title.to_string(),
+                };
+                let mut body = format!("Uploaded {} files", deployed.uploaded);
+                if let Some(url) = &manifest.url {
+                    body.push_str(&format!(", live at {url}"));
+                }
+                let notification = notify::Notification {
+                    subject: format!("Deployed {title}"),
+                    body,
+                };
+                notify::notify_all(&config.notify, &notification).await;
+            }
+        }
+        Action::Init {
+            manifest: file,
+            prefix,
+            title,
+            preset,
+            interactive,
+            force,
+        } => {
+            let (title, prefix) = if interactive {
+                (prompt("Title", &title)?, prompt("URL prefix", &prefix)?)
+            } else {
+                (title, prefix)
+            };
+            let manifest = match preset {
+                Some(preset) => preset.manifest(title, prefix),
+                None => Manifest {
+                    title,
+                    prefix,
+                    ..Default::default()
+                },
+            };
+            let template = serde_json::to_string_pretty(&manifest)
                 .context("Failed to serialize default manifest template")?;
-            std::fs::write(manifest, template).context("Failed to write template manifest")?;
+            let mut files = vec![(file.clone(), template)];
+            if preset.is_some() {
+                let directory = file.parent().unwrap_or(Path::new(""));
+                let name = file.file_name().context("Invalid manifest file name")?;
+                files.push((directory.join(config::FILE), init::config(Path::new(name))));
+                files.push((
+                    directory.join(init::STYLESHEET_FILE),
+                    init::STYLESHEET.to_string(),
+                ));
+            }
+            if !force {
+                if let Some((existing, _)) = files.iter().find(|(path, _)| path.exists()) {
+                    anyhow::bail!("{} exists, use --force to replace it", existing.display());
+                }
+            }
+            for (path, contents) in files {
+                if dry_run {
+                    println!("Would write {}", path.display());
+                    continue;
+                }
+                atomic::write(&path, contents)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                tracing::info!("Wrote {}", path.display());
+            }
+        }
+        Action::Serve {
+            manifest,
+            address,
+            throttle,
+        } => {
+            serve::serve(manifest, address, throttle).await?;
+        }
+        Action::Completions { shell } => {
+            let mut command = Arguments::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+        }
+        Action::Manpage { directory } => match directory {
+            Some(directory) => clap_mangen::generate_to(Arguments::command(), &directory)
+                .with_context(|| format!("Failed to write man pages to {}", directory.display()))?,
+            None => clap_mangen::Man::new(Arguments::command())
+                .render(&mut std::io::stdout())
+                .context("Failed to write man page")?,
+        },
+        Action::Benchmark { tracks, rounds } => {
+            println!("{}", benchmark::run(tracks, rounds).await?);
         }
     }
 
     Ok(())
 }
 
-fn song_url(prefix: &str, path: &str) -> String {
-    format!("{}{}", prefix, path)
+/// The song URLs of `manifest` as published, presigning `s3://` URLs for `expiry` seconds
+fn published_urls(manifest: &Manifest, expiry: u64) -> anyhow::Result<Vec<String>> {
+    let mut urls = manifest
+        .songs
+        .iter()
+        .map(|song| song_url(manifest, song))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if urls.iter().any(|url| s3::is_s3(url)) {
+        let credentials = s3::Credentials::from_env()?;
+        for url in urls.iter_mut().filter(|url| s3::is_s3(url)) {
+            *url = s3::presign(url, "GET", expiry, &credentials)?;
+        }
+    }
+    Ok(urls)
+}
+
+/// Check the songs of the manifest at `file` once, as described for [`Action::Check`]
+async fn check_manifest(
+    file: &Path,
+    verify: bool,
+    method: check::Method,
+    http: &http::Options,
+    max_failures: Option<usize>,
+    max_age: Option<chrono::Duration>,
+) -> anyhow::Result<(check::Summary, check::HostReport)> {
+    let manifest = Manifest::read(file)?;
+    let base = std::env::current_dir()
+        .context("Failed to get current directory")?
+        .join(file.parent().unwrap_or(Path::new("")));
+    let cache_path = base.join(check::CACHE_FILE);
+    let mut cache = check::Cache::load(&cache_path)?;
+    let now = Utc::now();
+    let mut summary = check::Summary::default();
+    let mut credentials = None;
+    let mut checked = Vec::new();
+    let mut targets = Vec::new();
+    for song in &manifest.songs {
+        for path in song.files() {
+            if !base.join(path).is_file() {
+                tracing::info!("missing {} of {}", path.display(), song.name);
+                summary.missing += 1;
+            }
+        }
+        // Cache by the URL without access tokens, which may change and should not be stored
+        let key = check::check_url(plain_song_url(&manifest, song)?, &base)?;
+        if max_age.is_some_and(|max_age| cache.is_fresh(&key, max_age, verify, now)) {
+            tracing::debug!("Skipping recently checked {key}");
+            summary.cached += 1;
+            continue;
+        }
+        tracing::debug!("Checking {key}");
+        let url = check::check_url(song_url(&manifest, song)?, &base)?;
+        let target = if s3::is_s3(&url) {
+            if credentials.is_none() {
+                credentials = Some(s3::Credentials::from_env()?);
+            }
+            let presigned = s3::presign(&url, "HEAD", 60, credentials.as_ref().unwrap())?;
+            check::Target::Presigned {
+                url: key.clone(),
+                presigned,
+            }
+        } else {
+            check::Target::Url {
+                url: key.clone(),
+                location: url.clone(),
+            }
+        };
+        checked.push((song, key, url));
+        targets.push(target);
+    }
+    let responses = check::check_all(targets, method, http, max_failures).await?;
+    summary.skipped = checked.len() - responses.len();
+    let mut report = check::HostReport::default();
+    for ((_, key, _), response) in checked.iter().zip(&responses) {
+        let outcome = check::Outcome::of(response.status());
+        summary.add(outcome);
+        report.add(response);
+        cache.record(key, response.status(), now);
+        if outcome != check::Outcome::Ok {
+            tracing::info!("not reachable {} ({})", response.0, response.status())
+        }
+    }
+    if verify && summary.skipped == 0 {
+        let client = &http.client()?;
+        let mut verifications = Vec::new();
+        for ((song, key, url), response) in checked.into_iter().zip(&responses) {
+            if !response.status().is_success() {
+                continue;
+            }
+            if song.hash.is_none() && song.size.is_none() {
+                cache.mark_verified(&key);
+                continue;
+            }
+            let download = match &credentials {
+                Some(credentials) if s3::is_s3(&url) => {
+                    s3::presign(&url, "GET", 60 * 60, credentials)?
+                }
+                _ => url,
+            };
+            verifications.push(async move {
+                let drift =
+                    compare::verify(client, &download, song.size, song.hash.as_deref()).await;
+                (song, key, drift)
+            });
+        }
+        for (song, key, drift) in futures::future::join_all(verifications).await {
+            match drift {
+                Ok(None) => cache.mark_verified(&key),
+                Ok(Some(drift)) => {
+                    summary.drifted += 1;
+                    tracing::warn!("{}: {drift}", song.name);
+                }
+                Err(e) => {
+                    summary.drifted += 1;
+                    tracing::warn!("{}: {e:#}", song.name);
+                }
+            }
+        }
+    }
+    cache.save(&cache_path)?;
+    Ok((summary, report))
+}
+
+/// The notification about the failures of checking the manifest at `file`
+fn outage(
+    file: &Path,
+    summary: &check::Summary,
+    report: &check::HostReport,
+) -> notify::Notification {
+    notify::Notification {
+        subject: format!("Tracks of {} are failing", file.display()),
+        body: format!("{report}{summary}"),
+    }
+}
+
+/// Ask `question` on the terminal, with `default` for an empty answer
+fn prompt(question: &str, default: &str) -> anyhow::Result<String> {
+    print!("{question} [{default}]: ");
+    std::io::stdout()
+        .flush()
+        .context("Failed to write prompt")?;
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .context("Failed to read answer")?;
+    Ok(match answer.trim() {
+        "" => default.to_string(),
+        answer => answer.to_string(),
+    })
+}
+
+/// Print the new `index` and stable ID of each affected song, one per line
+fn print_indices(manifest: &Manifest, indices: &[usize]) {
+    let ids = manifest.slugs();
+    for &index in indices {
+        println!("{index}\t{}", ids[index]);
+    }
 }
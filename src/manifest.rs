@@ -0,0 +1,1005 @@
+use age::{
+    armor::{ArmoredReader, ArmoredWriter, Format},
+    secrecy::Secret,
+};
+use anyhow::{bail, Context};
+use chrono::{DateTime, Utc};
+use percent_encoding::{percent_decode_str, percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::token;
+
+/// The environment variable holding the passphrase of encrypted manifests
+pub const PASSPHRASE_VAR: &str = "TRACKINATOR_PASSPHRASE";
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Song {
+    /// The title of the song
+    pub name: String,
+    /// The path of the song below the prefix, or the local file relative to the manifest
+    pub path: PathBuf,
+    /// The album this song belongs to, for per-album track numbers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    /// The track artist, if different from the album artist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    /// The year the song was first released
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    /// The series of an episode, like a podcast or audio drama
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+    /// The season of an episode within its series
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub season: Option<u32>,
+    /// The number of an episode within its season
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub episode: Option<u32>,
+    /// Free-form labels, like `live` or `acoustic`, to filter songs by
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// The MIME type, like `audio/ogg`, if it can't be told from the file extension
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    /// Overrides the manifest prefix for this song
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// The play time in seconds, as probed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    /// The file size in bytes, as probed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// The hex-encoded SHA-256 hash of the local file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// The `.lrc` file with time-synced lyrics, relative to the manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lyrics_lrc: Option<PathBuf>,
+    /// After this instant, the song is left out of generated outputs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unpublish_at: Option<DateTime<Utc>>,
+    /// When the song was added to the manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added: Option<DateTime<Utc>>,
+    /// Offer the song for listening only: no download link, and no saving from the player menu
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_download: bool,
+    /// Notes on the song, shown in a collapsible section under its player
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<Text>,
+    /// The lyrics, shown in a collapsible section under the player
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lyrics: Option<Text>,
+}
+
+/// A text in Markdown, inline or in a file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Text {
+    /// The Markdown itself, like `"Recorded *live* in Berlin"`
+    Inline(String),
+    /// A Markdown file relative to the manifest, like `{"file": "lyrics/song.md"}`
+    File { file: PathBuf },
+}
+
+impl Text {
+    /// The Markdown of the text, reading its file relative to `base`
+    pub fn markdown(&self, base: &Path) -> anyhow::Result<String> {
+        match self {
+            Text::Inline(markdown) => Ok(markdown.clone()),
+            Text::File { file } => {
+                let path = base.join(file);
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))
+            }
+        }
+    }
+
+    /// The file of the text, unless it is inline
+    pub fn file(&self) -> Option<&Path> {
+        match self {
+            Text::Inline(_) => None,
+            Text::File { file } => Some(file),
+        }
+    }
+}
+
+impl Song {
+    /// The local files included in the page of the song, relative to the manifest: its synced
+    /// lyrics, description and lyrics
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        let texts = [&self.description, &self.lyrics];
+        self.lyrics_lrc.as_deref().into_iter().chain(
+            texts
+                .into_iter()
+                .filter_map(|text| text.as_ref().and_then(Text::file)),
+        )
+    }
+
+    /// Whether the song is published as of `now`
+    pub fn is_published(&self, now: DateTime<Utc>) -> bool {
+        self.unpublish_at
+            .is_none_or(|unpublish_at| now < unpublish_at)
+    }
+
+    /// An identifier for this song, derived from its name
+    ///
+    /// Songs of the same name share it, [`Manifest::slugs`] are the unambiguous IDs.
+    pub fn id(&self) -> String {
+        slugify(&self.name)
+    }
+
+    /// Add `tags` the song does not have yet
+    pub fn add_tags(&mut self, tags: impl IntoIterator<Item = String>) {
+        for tag in tags {
+            if !self.tags.contains(&tag) {
+                self.tags.push(tag);
+            }
+        }
+    }
+
+    /// Order episodes by series, season and episode number, those without any of them last
+    pub fn cmp_episodes(&self, other: &Self) -> Ordering {
+        fn none_last<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (a, b) => a.is_none().cmp(&b.is_none()),
+            }
+        }
+        none_last(self.series.as_deref(), other.series.as_deref())
+            .then_with(|| none_last(self.season, other.season))
+            .then_with(|| none_last(self.episode, other.episode))
+    }
+}
+
+/// The version of the layout of a manifest
+///
+/// Manifests without version have the original flat layout of version 0. Older manifests are
+/// migrated to [`Version::CURRENT`] on load, and newer ones are rejected.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, JsonSchema,
+)]
+#[serde(transparent)]
+pub struct Version(pub u32);
+
+impl Version {
+    /// The version written by this version of trackinator
+    pub const CURRENT: Self = Version(1);
+}
+
+impl Default for Version {
+    fn default() -> Self {
+        Self::CURRENT
+    }
+}
+
+/// The upgrades of the manifest layout, the first from version 0 to 1 and so on
+const MIGRATIONS: &[fn(&mut serde_json::Map<String, serde_json::Value>)] = &[
+    // 1: The flat layout with a `version`
+    |_| {},
+];
+
+const _: () = assert!(MIGRATIONS.len() == Version::CURRENT.0 as usize);
+
+/// Upgrade the manifest `json` of layout `version` to the current one
+fn migrate(json: &[u8], version: Version) -> anyhow::Result<Vec<u8>> {
+    let mut manifest: serde_json::Value =
+        serde_json::from_slice(json).context("Failed to read manifest")?;
+    let fields = manifest
+        .as_object_mut()
+        .context("Failed to read manifest: not a JSON object")?;
+    for migration in &MIGRATIONS[version.0 as usize..] {
+        migration(fields);
+    }
+    fields.insert("version".to_string(), Version::CURRENT.0.into());
+    serde_json::to_vec(&manifest).context("Failed to migrate manifest")
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Manifest {
+    /// The layout version of the manifest
+    #[serde(default)]
+    pub version: Version,
+    /// The title of the page
+    pub title: String,
+    /// A short summary for search engines and link previews
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The public URL of the generated page, for link previews and the sitemap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The URL of the image shown in link previews, like the cover art
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// The album artist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    /// A compilation: show the artist per track rather than once for the site
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub various_artists: bool,
+    /// The URL prefix of the song paths, like `https://cdn.example.org/`, or empty for local files
+    pub prefix: String,
+    /// Song paths are already percent-encoded and are used verbatim
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encoded: bool,
+    /// Appended to `http` and `https` song URLs, like `?token={env:TRACK_TOKEN}` for hosts that
+    /// require an access token, see [`token`](crate::token) for the placeholders
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_suffix: Option<String>,
+    /// The default target of `Deploy`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deploy: Option<String>,
+    /// The color scheme of the generated page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<Theme>,
+    /// The accent color of the players, as CSS color like `#e0457b`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accent: Option<String>,
+    /// The language of the page, like `en` (the default) or `de-AT`, for its `lang` attribute and
+    /// the built-in texts in English, German (`de`), French (`fr`) or Spanish (`es`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// Texts of the page replacing the built-in ones, by key: `total_runtime`,
+    /// `download_album`, `download`, `new`, `season`, `description` and `lyrics`, with `{}` in
+    /// place of the value
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub strings: BTreeMap<String, String>,
+    /// A custom stylesheet included in the page after the theme, relative to the manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stylesheet: Option<PathBuf>,
+    /// MIME types by file extension, like `"mka": "audio/x-matroska"`, taking precedence over
+    /// the built-in ones
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mime_types: BTreeMap<String, String>,
+    /// Named selections of songs by ID, in playing order
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub playlists: BTreeMap<String, Vec<String>>,
+    /// Show track numbers in front of song names
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub numbering: Option<Numbering>,
+    /// Count listens by sending a beacon when a track starts playing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analytics: Option<Analytics>,
+    /// The songs, in playing order
+    pub songs: Vec<Song>,
+}
+
+/// A built-in color scheme of the generated page
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    /// Dark or light, following the `prefers-color-scheme` of the visitor
+    Auto,
+}
+
+impl Theme {
+    /// The class of the page body selecting this theme
+    pub fn class(self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+            Theme::Auto => "auto",
+        }
+    }
+}
+
+impl std::str::FromStr for Theme {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dark" => Ok(Theme::Dark),
+            "light" => Ok(Theme::Light),
+            "auto" => Ok(Theme::Auto),
+            _ => bail!("Unknown theme {s}, expected one of dark, light, auto"),
+        }
+    }
+}
+
+/// How track numbers are shown in generated outputs
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct Numbering {
+    /// Whether numbers count through all songs or restart per album
+    #[serde(default)]
+    pub scope: NumberingScope,
+    /// The minimum number of digits, padded with zeros
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub width: usize,
+    /// What goes between number and name
+    #[serde(default = "default_separator")]
+    pub separator: String,
+}
+
+impl Default for Numbering {
+    fn default() -> Self {
+        Self {
+            scope: NumberingScope::default(),
+            width: 0,
+            separator: default_separator(),
+        }
+    }
+}
+
+/// Where the generated page reports listens to
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct Analytics {
+    /// The URL a beacon is posted to when a track starts playing from the beginning, with
+    /// `{name}` and `{id}` replaced by the percent-encoded name and ID of the track, like
+    /// `https://stats.example.org/play?track={id}`
+    ///
+    /// The body is a JSON object with the `name` and `id` of the track.
+    pub beacon: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberingScope {
+    /// Number all songs in order
+    #[default]
+    Global,
+    /// Number the songs of each album separately, in order
+    Album,
+}
+
+fn default_separator() -> String {
+    ". ".to_string()
+}
+
+fn is_zero(width: &usize) -> bool {
+    *width == 0
+}
+
+/// `text` in lowercase, with runs of other characters than alphanumerics replaced by `-`
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Characters to percent-encode in a URL path segment: everything but the unreserved set
+pub const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// The URL of `song` as published, with the manifest `url_suffix` appended
+pub fn song_url(manifest: &Manifest, song: &Song) -> anyhow::Result<String> {
+    let url = plain_song_url(manifest, song)?;
+    match &manifest.url_suffix {
+        Some(suffix) => token::append(url, suffix),
+        None => Ok(url),
+    }
+}
+
+/// The URL of `song` without the `url_suffix`, percent-encoding each path segment unless the
+/// manifest is pre-encoded
+///
+/// The song's own prefix takes precedence over the manifest prefix. Paths that are not valid
+/// UTF-8 have their raw bytes percent-encoded where the platform allows, and are rejected
+/// otherwise.
+pub fn plain_song_url(manifest: &Manifest, song: &Song) -> anyhow::Result<String> {
+    let prefix = song.prefix.as_deref().unwrap_or(&manifest.prefix);
+    let invalid = || format!("Path of {} is not valid UTF-8", song.name);
+    if manifest.encoded {
+        let path = song.path.to_str().with_context(invalid)?;
+        return Ok(format!("{}{}", prefix, path));
+    }
+    let path = path_bytes(&song.path)
+        .with_context(invalid)?
+        .split(|&byte| byte == b'/')
+        .map(|segment| percent_encode(segment, PATH_SEGMENT).to_string())
+        .collect::<Vec<_>>()
+        .join("/");
+    Ok(format!("{}{}", prefix, path))
+}
+
+/// The local path of the percent-encoded relative URL `url`, the inverse of [`plain_song_url`]
+pub fn decode_path(url: &str) -> anyhow::Result<PathBuf> {
+    let bytes = percent_decode_str(url).collect::<Vec<u8>>();
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        Ok(std::ffi::OsString::from_vec(bytes).into())
+    }
+    #[cfg(not(unix))]
+    {
+        let path = String::from_utf8(bytes).with_context(|| format!("Invalid local path {url}"))?;
+        Ok(path.into())
+    }
+}
+
+/// The raw bytes of `path`, or with no platform representation its UTF-8 bytes if valid
+fn path_bytes(path: &Path) -> Option<&[u8]> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Some(path.as_os_str().as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        path.to_str().map(str::as_bytes)
+    }
+}
+
+/// Infer the MIME type of an audio file from its extension
+fn mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let mime = match extension.as_str() {
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        "m4a" => "audio/mp4",
+        "wav" => "audio/wav",
+        "opus" => "audio/ogg; codecs=opus",
+        "oga" => "audio/ogg",
+        "aac" => "audio/aac",
+        "mp4" => "audio/mp4",
+        "webm" | "weba" => "audio/webm",
+        "aif" | "aiff" => "audio/aiff",
+        "mka" => "audio/x-matroska",
+        _ => return None,
+    };
+    Some(mime)
+}
+
+/// `path` with `\\` separators replaced by `/`, `.` segments removed and `..` segments resolved
+///
+/// Paths with `..` segments escaping the manifest directory are rejected.
+pub fn normalize_path(path: &Path) -> anyhow::Result<PathBuf> {
+    let Some(text) = path.to_str() else {
+        // Paths that are not UTF-8 have no `\\` separators to replace
+        let mut normalized = PathBuf::new();
+        let mut depth = 0;
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if depth == 0 {
+                        bail!(
+                            "Path {} must not leave its directory with `..`",
+                            path.display()
+                        );
+                    }
+                    normalized.pop();
+                    depth -= 1;
+                }
+                Component::Normal(segment) => {
+                    normalized.push(segment);
+                    depth += 1;
+                }
+                root => normalized.push(root.as_os_str()),
+            }
+        }
+        return Ok(normalized);
+    };
+    let text = text.replace('\\', "/");
+    let mut segments = Vec::new();
+    for segment in text.split('/') {
+        match segment {
+            "" | "." => {}
+            // A leading drive like `C:` is the root of Windows paths, which `..` cannot leave
+            ".." => match segments.pop() {
+                Some(segment) if !(segments.is_empty() && is_drive(segment)) => {}
+                _ => bail!("Path {text} must not leave its directory with `..`"),
+            },
+            segment => segments.push(segment),
+        }
+    }
+    if segments.is_empty() {
+        bail!("Path {text} is empty");
+    }
+    let normalized = segments.join("/");
+    if text.starts_with('/') {
+        Ok(format!("/{normalized}").into())
+    } else {
+        Ok(normalized.into())
+    }
+}
+
+/// Whether `segment` is a Windows drive, like `C:`
+pub(crate) fn is_drive(segment: &str) -> bool {
+    segment.len() == 2
+        && segment.ends_with(':')
+        && segment.starts_with(|c: char| c.is_ascii_alphabetic())
+}
+
+/// How a manifest is stored on disk
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    /// Plain JSON
+    #[default]
+    Plain,
+    /// JSON encrypted with an age passphrase, ASCII armored
+    Encrypted,
+}
+
+impl Manifest {
+    /// Read the manifest at `path`, decrypting it in memory if it is encrypted
+    ///
+    /// Fields this version does not know are ignored, so that manifests written by newer
+    /// versions can still be read.
+    pub fn load(path: &Path) -> anyhow::Result<(Self, Storage)> {
+        Self::load_with(path, false)
+    }
+
+    /// Read the manifest at `path` like [`Manifest::load`], rejecting unknown fields
+    ///
+    /// This catches typos like `prefx`, which are otherwise silently ignored.
+    pub fn load_strict(path: &Path) -> anyhow::Result<(Self, Storage)> {
+        Self::load_with(path, true)
+    }
+
+    fn load_with(path: &Path, strict: bool) -> anyhow::Result<(Self, Storage)> {
+        let bytes = std::fs::read(path).context("Failed to open manifest")?;
+        Self::parse(bytes, strict)
+    }
+
+    /// Read the manifest file contents `bytes` like [`Manifest::load`], or with `strict` like
+    /// [`Manifest::load_strict`]
+    pub fn parse(bytes: Vec<u8>, strict: bool) -> anyhow::Result<(Self, Storage)> {
+        let (json, storage) = if bytes.trim_ascii_start().starts_with(b"{") {
+            (bytes, Storage::Plain)
+        } else {
+            let json = decrypt(&bytes).context("Failed to decrypt manifest")?;
+            (json, Storage::Encrypted)
+        };
+        let json = match Manifest::version_of(&json)? {
+            version if version == Version::CURRENT => json,
+            version if version > Version::CURRENT => bail!(
+                "Manifest version {} is newer than the supported version {}, update trackinator",
+                version.0,
+                Version::CURRENT.0
+            ),
+            version => {
+                tracing::info!(
+                    "Migrating manifest from version {} to {}",
+                    version.0,
+                    Version::CURRENT.0
+                );
+                migrate(&json, version)?
+            }
+        };
+        let mut unknown = Vec::new();
+        let mut deserializer = serde_json::Deserializer::from_slice(&json);
+        let mut manifest: Self = if strict {
+            serde_ignored::deserialize(&mut deserializer, |path| unknown.push(path.to_string()))
+        } else {
+            Self::deserialize(&mut deserializer)
+        }
+        .context("Failed to read manifest")?;
+        if !unknown.is_empty() {
+            anyhow::bail!("Unknown fields in manifest: {}", unknown.join(", "));
+        }
+        manifest.normalize_paths()?;
+        Ok((manifest, storage))
+    }
+
+    /// The JSON Schema of manifests, for editors and other tools validating them
+    pub fn json_schema() -> schemars::schema::RootSchema {
+        schemars::schema_for!(Manifest)
+    }
+
+    /// The layout version of the manifest `json`, without reading the rest
+    pub fn version_of(json: &[u8]) -> anyhow::Result<Version> {
+        #[derive(Deserialize)]
+        struct Header {
+            version: Option<Version>,
+        }
+        let header: Header = serde_json::from_slice(json).context("Failed to read manifest")?;
+        Ok(header.version.unwrap_or(Version(0)))
+    }
+
+    /// Normalize the song paths to `/` separators without `.` and `..` segments
+    fn normalize_paths(&mut self) -> anyhow::Result<()> {
+        for (index, song) in self.songs.iter_mut().enumerate() {
+            let context = || format!("Invalid song {index} ({})", song.name);
+            song.path = normalize_path(&song.path).with_context(context)?;
+            if let Some(lyrics) = &song.lyrics_lrc {
+                song.lyrics_lrc = Some(normalize_path(lyrics).with_context(context)?);
+            }
+            for text in [&mut song.description, &mut song.lyrics]
+                .into_iter()
+                .flatten()
+            {
+                if let Text::File { file } = text {
+                    *file = normalize_path(file).with_context(context)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The artist of `song`: its track artist, else the album artist
+    pub fn artist_of<'a>(&'a self, song: &'a Song) -> Option<&'a str> {
+        song.artist.as_deref().or(self.artist.as_deref())
+    }
+
+    /// The song IDs, suffixed with `-2`, `-3`, … where they would be ambiguous
+    pub fn slugs(&self) -> Vec<String> {
+        let mut seen = std::collections::HashSet::new();
+        self.songs
+            .iter()
+            .map(|song| {
+                let id = song.id();
+                let mut slug = id.clone();
+                let mut n = 1;
+                while !seen.insert(slug.clone()) {
+                    n += 1;
+                    slug = format!("{id}-{n}");
+                }
+                slug
+            })
+            .collect()
+    }
+
+    /// The track number of each song, if `numbering` is set
+    pub fn track_numbers(&self) -> Option<Vec<usize>> {
+        let numbering = self.numbering.as_ref()?;
+        let mut counts = std::collections::HashMap::new();
+        let numbers = self
+            .songs
+            .iter()
+            .map(|song| {
+                let album = match numbering.scope {
+                    NumberingScope::Global => None,
+                    NumberingScope::Album => song.album.as_deref(),
+                };
+                let count = counts.entry(album).or_insert(0);
+                *count += 1;
+                *count
+            })
+            .collect();
+        Some(numbers)
+    }
+
+    /// The song names as shown in generated outputs, prefixed by their track numbers if set
+    pub fn display_names(&self) -> Vec<String> {
+        let (Some(numbering), Some(numbers)) = (&self.numbering, self.track_numbers()) else {
+            return self.songs.iter().map(|song| song.name.clone()).collect();
+        };
+        self.songs
+            .iter()
+            .zip(numbers)
+            .map(|(song, number)| {
+                format!(
+                    "{number:0width$}{}{}",
+                    numbering.separator,
+                    song.name,
+                    width = numbering.width
+                )
+            })
+            .collect()
+    }
+
+    /// Read the manifest at `path`, regardless of its storage
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        Self::load(path).map(|(manifest, _)| manifest)
+    }
+
+    /// Write the manifest to `path` as pretty-printed JSON, encrypting it according to `storage`
+    pub fn save(&self, path: &Path, storage: Storage) -> anyhow::Result<()> {
+        let bytes = self.to_bytes(storage)?;
+        crate::atomic::write(path, bytes).context("Failed to write manifest")
+    }
+
+    /// The file contents the manifest is saved as with `storage`
+    pub fn to_bytes(&self, storage: Storage) -> anyhow::Result<Vec<u8>> {
+        let json = self.to_json()?;
+        Ok(match storage {
+            Storage::Plain => json.into_bytes(),
+            Storage::Encrypted => encrypt(json.as_bytes()).context("Failed to encrypt manifest")?,
+        })
+    }
+
+    /// The pretty-printed JSON the manifest is saved as
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        for (index, song) in self.songs.iter().enumerate() {
+            let paths = std::iter::once(song.path.as_path()).chain(song.files());
+            if let Some(path) = paths.into_iter().find(|path| path.to_str().is_none()) {
+                bail!(
+                    "Invalid song {index} ({}): path {} is not valid UTF-8 and cannot be saved",
+                    song.name,
+                    path.display()
+                );
+            }
+        }
+        serde_json::to_string_pretty(self).context("Failed to serialize manifest")
+    }
+
+    /// A unified diff from the manifest currently at `path` to this one, decrypted if need be
+    ///
+    /// A missing manifest diffs as empty.
+    pub fn diff(&self, path: &Path) -> anyhow::Result<String> {
+        let old = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).context("Failed to open manifest"),
+        };
+        self.diff_from(&old, &path.display().to_string())
+    }
+
+    /// A unified diff from the manifest file contents `old`, named `name`, to this one
+    pub fn diff_from(&self, old: &[u8], name: &str) -> anyhow::Result<String> {
+        let old = if old.is_empty() || old.trim_ascii_start().starts_with(b"{") {
+            old.to_vec()
+        } else {
+            decrypt(old).context("Failed to decrypt manifest")?
+        };
+        let old = String::from_utf8(old).context("Failed to read manifest")?;
+        let new = self.to_json()?;
+        Ok(similar::TextDiff::from_lines(&old, &new)
+            .unified_diff()
+            .header(name, name)
+            .to_string())
+    }
+
+    /// The explicit MIME type of `song`, or else the one configured or built in for its file
+    /// extension
+    pub fn mime_of<'a>(&'a self, song: &'a Song) -> Option<&'a str> {
+        if let Some(mime) = &song.mime {
+            return Some(mime);
+        }
+        let extension = song.path.extension()?.to_str()?;
+        self.mime_types
+            .iter()
+            .find(|(other, _)| {
+                other
+                    .trim_start_matches('.')
+                    .eq_ignore_ascii_case(extension)
+            })
+            .map(|(_, mime)| mime.as_str())
+            .or_else(|| mime_type(&song.path))
+    }
+
+    /// Keep only the songs of the playlist `name`, in its order
+    pub fn select_playlist(&mut self, name: &str) -> anyhow::Result<()> {
+        let ids = self
+            .playlists
+            .get(name)
+            .with_context(|| format!("No playlist {name}"))?;
+        let songs = ids
+            .iter()
+            .map(|id| {
+                let index = self
+                    .find(id)
+                    .with_context(|| format!("Invalid song in playlist {name}"))?;
+                Ok(self.songs[index].clone())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        self.songs = songs;
+        Ok(())
+    }
+
+    /// Put the songs in `order`, a permutation of their indices, updating the IDs in playlists
+    pub fn reorder(&mut self, order: &[usize]) {
+        let before = self.slugs();
+        let mut moved = vec![0; order.len()];
+        for (new, &old) in order.iter().enumerate() {
+            moved[old] = new;
+        }
+        self.songs = order
+            .iter()
+            .map(|&index| self.songs[index].clone())
+            .collect();
+        self.update_playlists(&before, |old| Some(moved[old]));
+    }
+
+    /// Update the song IDs in playlists after changing the songs, which had the IDs `before`
+    ///
+    /// `moved` is the new index of the song at each old index, `None` if it was removed, whose
+    /// ID is dropped from the playlists.
+    pub fn update_playlists(&mut self, before: &[String], moved: impl Fn(usize) -> Option<usize>) {
+        let after = self.slugs();
+        let renames = before
+            .iter()
+            .enumerate()
+            .map(|(index, id)| (id.as_str(), moved(index).map(|index| &after[index])))
+            .collect::<BTreeMap<_, _>>();
+        for ids in self.playlists.values_mut() {
+            ids.retain_mut(|id| match renames.get(id.as_str()) {
+                Some(Some(new)) => {
+                    *id = new.to_string();
+                    true
+                }
+                Some(None) => false,
+                None => true,
+            });
+        }
+        self.playlists.retain(|_, ids| !ids.is_empty());
+    }
+
+    /// The index of the song selected by `selector`, either its index or its ID
+    pub fn find(&self, selector: &str) -> anyhow::Result<usize> {
+        if let Ok(index) = selector.parse::<usize>() {
+            if index >= self.songs.len() {
+                bail!(
+                    "Index {index} is out of bounds for {} songs",
+                    self.songs.len()
+                );
+            }
+            return Ok(index);
+        }
+        self.slugs()
+            .iter()
+            .position(|id| id == selector)
+            .with_context(|| format!("No song with ID {selector}"))
+    }
+}
+
+fn passphrase() -> anyhow::Result<Secret<String>> {
+    match std::env::var(PASSPHRASE_VAR) {
+        Ok(passphrase) if !passphrase.is_empty() => Ok(Secret::new(passphrase)),
+        _ => bail!("Encrypted manifests need a passphrase in ${PASSPHRASE_VAR}"),
+    }
+}
+
+fn encrypt(plain: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let encryptor = age::Encryptor::with_user_passphrase(passphrase()?);
+    let mut encrypted = Vec::new();
+    let armor = ArmoredWriter::wrap_output(&mut encrypted, Format::AsciiArmor)?;
+    let mut writer = encryptor.wrap_output(armor)?;
+    writer.write_all(plain)?;
+    writer.finish()?.finish()?;
+    Ok(encrypted)
+}
+
+fn decrypt(encrypted: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let decryptor = match age::Decryptor::new(ArmoredReader::new(encrypted))? {
+        age::Decryptor::Passphrase(decryptor) => decryptor,
+        age::Decryptor::Recipients(_) => bail!("Only passphrase-encrypted manifests are supported"),
+    };
+    let mut reader = decryptor.decrypt(&passphrase()?, None)?;
+    let mut plain = Vec::new();
+    reader.read_to_end(&mut plain)?;
+    Ok(plain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(path: &str) -> anyhow::Result<String> {
+        Ok(normalize_path(Path::new(path))?
+            .to_string_lossy()
+            .into_owned())
+    }
+
+    #[test]
+    fn normalize_path_cleans_segments() {
+        assert_eq!(normalized("./a//b/../c.mp3").unwrap(), "a/c.mp3");
+        assert_eq!(normalized("/music/./a.mp3").unwrap(), "/music/a.mp3");
+        assert_eq!(
+            normalized("Music\\Live\\a.mp3").unwrap(),
+            "Music/Live/a.mp3"
+        );
+        assert_eq!(normalized("C:\\Music\\..\\a.mp3").unwrap(), "C:/a.mp3");
+    }
+
+    #[test]
+    fn normalize_path_rejects_leaving_the_directory() {
+        assert!(normalized("../a.mp3").is_err());
+        assert!(normalized("a/../../b.mp3").is_err());
+        assert!(normalized("/../a.mp3").is_err());
+        assert!(normalized("C:\\..\\a.mp3").is_err());
+        assert!(normalized("a/..").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn normalize_path_checks_paths_that_are_not_utf8() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        fn path(bytes: &[u8]) -> &Path {
+            Path::new(OsStr::from_bytes(bytes))
+        }
+        assert_eq!(
+            normalize_path(path(b"./a/\xff/../b.mp3\xff")).unwrap(),
+            path(b"a/b.mp3\xff")
+        );
+        assert!(normalize_path(path(b"a/../../\xff.mp3")).is_err());
+    }
+
+    fn manifest(names: &[&str]) -> Manifest {
+        Manifest {
+            songs: names
+                .iter()
+                .map(|name| Song {
+                    name: name.to_string(),
+                    path: PathBuf::from(format!("{}.mp3", name.to_lowercase())),
+                    ..Song::default()
+                })
+                .collect(),
+            ..Manifest::default()
+        }
+    }
+
+    fn parse(json: &str, strict: bool) -> anyhow::Result<Manifest> {
+        Ok(Manifest::parse(json.as_bytes().to_vec(), strict)?.0)
+    }
+
+    #[test]
+    fn unversioned_manifests_are_migrated() {
+        let json = r#"{"title": "T", "prefix": "p/", "songs": [{"name": "A", "path": "a.mp3"}]}"#;
+        assert_eq!(Manifest::version_of(json.as_bytes()).unwrap(), Version(0));
+        let manifest = parse(json, true).unwrap();
+        assert_eq!(manifest.version, Version::CURRENT);
+        assert_eq!(manifest.songs[0].path, Path::new("a.mp3"));
+        let migrated = migrate(json.as_bytes(), Version(0)).unwrap();
+        assert_eq!(Manifest::version_of(&migrated).unwrap(), Version::CURRENT);
+    }
+
+    #[test]
+    fn newer_manifests_are_rejected() {
+        let json = format!(
+            r#"{{"version": {}, "title": "T", "prefix": "", "songs": []}}"#,
+            Version::CURRENT.0 + 1
+        );
+        let error = parse(&json, false).unwrap_err().to_string();
+        assert!(error.contains("newer"), "{error}");
+    }
+
+    #[test]
+    fn migrations_need_an_object() {
+        assert!(migrate(b"[]", Version(0)).is_err());
+        assert!(parse("[]", false).is_err());
+    }
+
+    #[test]
+    fn slugs_are_unique() {
+        let manifest = manifest(&["A", "Über Song!", "A", "A 2", "A", ""]);
+        assert_eq!(
+            manifest.slugs(),
+            ["a", "über-song", "a-2", "a-2-2", "a-3", ""]
+        );
+    }
+
+    #[test]
+    fn update_playlists_renames_and_drops_ids() {
+        let mut manifest = manifest(&["A", "B", "A"]);
+        let before = manifest.slugs();
+        manifest.playlists.insert(
+            "all".to_string(),
+            vec!["a-2".to_string(), "b".to_string(), "gone".to_string()],
+        );
+        manifest
+            .playlists
+            .insert("first".to_string(), vec!["a".to_string()]);
+        manifest.songs.remove(0);
+        manifest.update_playlists(&before, |old| old.checked_sub(1));
+        assert_eq!(manifest.playlists["all"], ["a", "b", "gone"]);
+        assert!(!manifest.playlists.contains_key("first"));
+    }
+
+    #[test]
+    fn reorder_keeps_playlists_on_songs_of_the_same_name() {
+        let mut manifest = manifest(&["A", "B", "A"]);
+        manifest.songs[2].album = Some("Live".to_string());
+        manifest
+            .playlists
+            .insert("live".to_string(), vec!["a-2".to_string(), "b".to_string()]);
+        manifest.reorder(&[2, 1, 0]);
+        assert_eq!(manifest.songs[0].album.as_deref(), Some("Live"));
+        assert_eq!(manifest.playlists["live"], ["a", "b"]);
+        let live = manifest.find(&manifest.playlists["live"][0]).unwrap();
+        assert_eq!(manifest.songs[live].album.as_deref(), Some("Live"));
+    }
+}
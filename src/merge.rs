@@ -0,0 +1,279 @@
+//! Combining several manifests into one
+
+use anyhow::{bail, Context};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use url::Url;
+
+use crate::manifest::{song_url, Manifest, Text};
+
+/// What to do with songs that several inputs share, by URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplicates {
+    /// Fail the merge
+    Error,
+    /// Leave out all copies of the song
+    Skip,
+    /// Keep the copy of the first input
+    PreferFirst,
+}
+
+impl FromStr for Duplicates {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Duplicates::Error),
+            "skip" => Ok(Duplicates::Skip),
+            "prefer-first" => Ok(Duplicates::PreferFirst),
+            _ => bail!("Unknown duplicate handling {s}, expected one of error, skip, prefer-first"),
+        }
+    }
+}
+
+/// Merge the manifests `inputs`, read from their paths, into one saved in `directory`
+///
+/// * Songs keep their URLs: inputs with a different prefix than the first one keep theirs
+///   per song, and local paths are rebased onto `directory`, which must contain them.
+/// * Songs without album get the title of their input as album, so that the inputs remain
+///   separate sections of the page.
+/// * If the inputs have different artists, the result is a compilation of the songs with
+///   their input's artist.
+///
+/// The other settings, like the title and theme, are taken from the first input.
+pub fn merge(
+    inputs: Vec<(PathBuf, Manifest)>,
+    directory: &Path,
+    duplicates: Duplicates,
+) -> anyhow::Result<Manifest> {
+    let Some((_, first)) = inputs.first() else {
+        bail!("Nothing to merge");
+    };
+    if inputs
+        .iter()
+        .any(|(_, input)| input.encoded != first.encoded)
+    {
+        bail!("Cannot merge manifests with and without pre-encoded paths");
+    }
+    let various_artists = inputs
+        .iter()
+        .any(|(_, input)| input.various_artists || input.artist != first.artist);
+    let mut merged = Manifest {
+        various_artists,
+        artist: (!various_artists).then(|| first.artist.clone()).flatten(),
+        songs: Vec::new(),
+        playlists: Default::default(),
+        ..first.clone()
+    };
+    let directory = canonical(directory)?;
+
+    let mut urls = Vec::new();
+    // The merged indices of the songs of each playlist, as IDs may change among more songs
+    let mut playlists = BTreeMap::<String, Vec<usize>>::new();
+    for (path, input) in inputs {
+        let ids = input.slugs();
+        let offset = merged.songs.len();
+        for (name, entries) in &input.playlists {
+            let indices = entries.iter().filter_map(|entry| {
+                let index = ids.iter().position(|id| id == entry);
+                if index.is_none() {
+                    tracing::warn!("Dropping unknown song {entry} from playlist {name}");
+                }
+                index.map(|index| offset + index)
+            });
+            playlists.entry(name.clone()).or_default().extend(indices);
+        }
+        let base = canonical(path.parent().unwrap_or(Path::new("")))?;
+        let rebase = |file: &Path| -> anyhow::Result<PathBuf> {
+            let relative = base.strip_prefix(&directory).with_context(|| {
+                format!(
+                    "Local files of {} are outside of {}",
+                    path.display(),
+                    directory.display()
+                )
+            })?;
+            Ok(relative.join(file))
+        };
+        let title = match input.title.as_str() {
+            "" => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned()),
+            title => Some(title.to_string()),
+        };
+        for mut song in input.songs.iter().cloned() {
+            let prefix = song.prefix.clone().unwrap_or_else(|| input.prefix.clone());
+            if Url::parse(&song_url(&input, &song)?).is_err() {
+                song.path = rebase(&song.path)?;
+            }
+            song.prefix = (prefix != merged.prefix).then_some(prefix);
+            if let Some(lyrics) = &song.lyrics_lrc {
+                song.lyrics_lrc = Some(rebase(lyrics)?);
+            }
+            for text in [&mut song.description, &mut song.lyrics]
+                .into_iter()
+                .flatten()
+            {
+                if let Text::File { file } = text {
+                    *file = rebase(file)?;
+                }
+            }
+            if song.album.is_none() {
+                song.album = title.clone();
+            }
+            if various_artists && song.artist.is_none() {
+                song.artist = input.artist.clone();
+            }
+            urls.push(song_url(&merged, &song)?);
+            merged.songs.push(song);
+        }
+    }
+
+    let mut counts = HashMap::<&str, usize>::new();
+    for url in &urls {
+        *counts.entry(url).or_default() += 1;
+    }
+    let mut kept = HashMap::<&str, bool>::new();
+    let mut keep = Vec::with_capacity(urls.len());
+    for (song, url) in merged.songs.iter().zip(&urls) {
+        if counts[url.as_str()] == 1 {
+            keep.push(true);
+            continue;
+        }
+        match duplicates {
+            Duplicates::Error => bail!("{} ({url}) is in several inputs", song.name),
+            Duplicates::Skip => {
+                if kept.insert(url, false).is_none() {
+                    tracing::warn!("Skipping {} ({url}), which is in several inputs", song.name);
+                }
+                keep.push(false);
+            }
+            Duplicates::PreferFirst => keep.push(!kept.insert(url, true).unwrap_or(false)),
+        }
+    }
+    // Entries of left out duplicates refer to the kept copy, if any
+    let mut moved = Vec::with_capacity(keep.len());
+    let mut kept_at = HashMap::<&str, usize>::new();
+    let mut count = 0;
+    for (url, keep) in urls.iter().zip(&keep) {
+        if *keep {
+            kept_at.insert(url, count);
+            moved.push(Some(count));
+            count += 1;
+        } else {
+            moved.push(None);
+        }
+    }
+    let moved = moved
+        .into_iter()
+        .zip(&urls)
+        .map(|(index, url)| index.or_else(|| kept_at.get(url.as_str()).copied()))
+        .collect::<Vec<_>>();
+    let mut keep = keep.into_iter();
+    merged.songs.retain(|_| keep.next().unwrap_or(true));
+    let ids = merged.slugs();
+    merged.playlists = playlists
+        .into_iter()
+        .map(|(name, indices)| {
+            let entries = indices
+                .into_iter()
+                .filter_map(|index| moved[index].map(|index| ids[index].clone()))
+                .collect::<Vec<_>>();
+            (name, entries)
+        })
+        .filter(|(_, entries)| !entries.is_empty())
+        .collect();
+    Ok(merged)
+}
+
+fn canonical(directory: &Path) -> anyhow::Result<PathBuf> {
+    let directory = if directory.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        directory
+    };
+    directory
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", directory.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Song;
+
+    /// An input of remote songs named and located by `songs`, with `playlists`
+    fn input(songs: &[(&str, &str)], playlists: &[(&str, &[&str])]) -> (PathBuf, Manifest) {
+        let manifest = Manifest {
+            prefix: "https://example.com/".to_string(),
+            songs: songs
+                .iter()
+                .map(|(name, path)| Song {
+                    name: name.to_string(),
+                    path: PathBuf::from(path),
+                    ..Song::default()
+                })
+                .collect(),
+            playlists: playlists
+                .iter()
+                .map(|(name, ids)| {
+                    (
+                        name.to_string(),
+                        ids.iter().map(|id| id.to_string()).collect(),
+                    )
+                })
+                .collect(),
+            ..Manifest::default()
+        };
+        (PathBuf::from("input.json"), manifest)
+    }
+
+    #[test]
+    fn playlists_follow_renamed_ids() {
+        let inputs = vec![
+            input(&[("A", "1.mp3")], &[("both", &["a"])]),
+            input(&[("A", "2.mp3")], &[("both", &["a"]), ("second", &["a"])]),
+        ];
+        let merged = merge(inputs, Path::new(""), Duplicates::Error).unwrap();
+        assert_eq!(merged.slugs(), ["a", "a-2"]);
+        assert_eq!(merged.playlists["both"], ["a", "a-2"]);
+        assert_eq!(merged.playlists["second"], ["a-2"]);
+    }
+
+    #[test]
+    fn playlists_refer_to_the_kept_duplicate() {
+        let inputs = vec![
+            input(&[("A", "a.mp3")], &[]),
+            input(
+                &[("B", "b.mp3"), ("A", "a.mp3")],
+                &[("second", &["a", "b"])],
+            ),
+        ];
+        let merged = merge(inputs, Path::new(""), Duplicates::PreferFirst).unwrap();
+        assert_eq!(merged.slugs(), ["a", "b"]);
+        assert_eq!(merged.playlists["second"], ["a", "b"]);
+    }
+
+    #[test]
+    fn skipped_duplicates_leave_playlists() {
+        let inputs = vec![
+            input(&[("A", "a.mp3")], &[("first", &["a"])]),
+            input(
+                &[("B", "b.mp3"), ("A", "a.mp3")],
+                &[("second", &["a", "b", "c"])],
+            ),
+        ];
+        let merged = merge(inputs, Path::new(""), Duplicates::Skip).unwrap();
+        assert_eq!(merged.slugs(), ["b"]);
+        assert!(!merged.playlists.contains_key("first"));
+        assert_eq!(merged.playlists["second"], ["b"]);
+    }
+
+    #[test]
+    fn duplicates_fail_by_default() {
+        let inputs = vec![input(&[("A", "a.mp3")], &[]), input(&[("A", "a.mp3")], &[])];
+        assert!(merge(inputs, Path::new(""), Duplicates::Error).is_err());
+    }
+}
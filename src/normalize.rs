@@ -0,0 +1,156 @@
+//! Cleaning up song paths of manifests edited on different systems
+
+use std::path::{Path, PathBuf};
+
+use crate::manifest::{is_drive, normalize_path, Manifest, Text};
+
+/// How to normalize the paths of a manifest
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// The directory absolute paths are made relative to, usually the one of the manifest
+    pub base: Option<PathBuf>,
+    /// Whether to lowercase the extensions of song paths, like `Intro.MP3` to `Intro.mp3`
+    pub lowercase_extensions: bool,
+}
+
+/// Rewrite the song paths, synced lyrics and text files of `manifest` to `/` separators
+/// without `.` and `..` segments, relative to `options.base`
+///
+/// Absolute paths, also Windows ones like `C:\Music\a.mp3`, are kept unless they are below the
+/// base. Only the manifest is changed, not the files.
+pub fn normalize(manifest: &mut Manifest, options: &Options) -> anyhow::Result<()> {
+    let base = options
+        .base
+        .as_deref()
+        .map(normalize_path)
+        .transpose()?
+        .map(|base| segments(&base));
+    for song in &mut manifest.songs {
+        let texts = [&mut song.description, &mut song.lyrics]
+            .into_iter()
+            .flatten()
+            .filter_map(|text| match text {
+                Text::File { file } => Some(file),
+                Text::Inline(_) => None,
+            });
+        for (is_song, path) in [(true, &mut song.path)]
+            .into_iter()
+            .chain(song.lyrics_lrc.iter_mut().map(|path| (false, path)))
+            .chain(texts.map(|path| (false, path)))
+        {
+            let mut normalized = normalize_path(path)?;
+            if let Some(base) = &base {
+                normalized = relative(&normalized, base);
+            }
+            if is_song && options.lowercase_extensions {
+                if let Some(extension) = normalized.extension().and_then(|e| e.to_str()) {
+                    normalized.set_extension(extension.to_lowercase());
+                }
+            }
+            *path = normalized;
+        }
+    }
+    Ok(())
+}
+
+/// The segments of the normalized `path`, with a leading `/` or drive like `C:` as the first
+fn segments(path: &Path) -> Vec<String> {
+    let text = path.to_string_lossy();
+    let mut segments = text.split('/').map(str::to_string).collect::<Vec<_>>();
+    if let [first, ..] = segments.as_mut_slice() {
+        if first.is_empty() {
+            *first = "/".to_string();
+        }
+    }
+    segments
+}
+
+/// Whether the first segment of a path makes it absolute
+fn is_root(segment: &str) -> bool {
+    segment == "/" || is_drive(segment)
+}
+
+/// The normalized `path` relative to the `base` segments, if it is absolute and below it
+fn relative(path: &Path, base: &[String]) -> PathBuf {
+    let segments = segments(path);
+    if !segments.first().is_some_and(|first| is_root(first)) {
+        return path.to_path_buf();
+    }
+    // Drive letters and Windows paths are case-insensitive
+    let windows = base.first().is_some_and(|first| first != "/");
+    let below = base.len() < segments.len()
+        && base.iter().zip(&segments).all(|(a, b)| match windows {
+            true => a.eq_ignore_ascii_case(b),
+            false => a == b,
+        });
+    if !below {
+        tracing::warn!(
+            "Keeping absolute path {}, it is not below {}",
+            path.display(),
+            base.join("/").replacen("//", "/", 1)
+        );
+        return path.to_path_buf();
+    }
+    segments[base.len()..].join("/").into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relative_to(path: &str, base: &str) -> PathBuf {
+        relative(Path::new(path), &segments(Path::new(base)))
+    }
+
+    #[test]
+    fn absolute_paths_below_the_base_become_relative() {
+        assert_eq!(
+            relative_to("/music/live/a.mp3", "/music"),
+            Path::new("live/a.mp3")
+        );
+        assert_eq!(
+            relative_to("c:/Music/a.mp3", "C:/music"),
+            Path::new("a.mp3")
+        );
+    }
+
+    #[test]
+    fn other_paths_are_kept() {
+        assert_eq!(relative_to("live/a.mp3", "/music"), Path::new("live/a.mp3"));
+        assert_eq!(
+            relative_to("/other/a.mp3", "/music"),
+            Path::new("/other/a.mp3")
+        );
+        assert_eq!(
+            relative_to("/Music/a.mp3", "/music"),
+            Path::new("/Music/a.mp3")
+        );
+        assert_eq!(relative_to("/music", "/music"), Path::new("/music"));
+        assert_eq!(
+            relative_to("D:/Music/a.mp3", "C:/Music"),
+            Path::new("D:/Music/a.mp3")
+        );
+    }
+
+    #[test]
+    fn normalize_rewrites_all_paths() {
+        let mut manifest = Manifest {
+            songs: vec![crate::manifest::Song {
+                path: PathBuf::from("C:\\Music\\.\\Live\\A.MP3"),
+                lyrics_lrc: Some(PathBuf::from("C:\\Music\\a.lrc")),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let options = Options {
+            base: Some(PathBuf::from("C:\\Music")),
+            lowercase_extensions: true,
+        };
+        normalize(&mut manifest, &options).unwrap();
+        assert_eq!(manifest.songs[0].path, Path::new("Live/A.mp3"));
+        assert_eq!(
+            manifest.songs[0].lyrics_lrc.as_deref(),
+            Some(Path::new("a.lrc"))
+        );
+    }
+}
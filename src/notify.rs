@@ -0,0 +1,195 @@
+//! Notifications about outages and deploys, sent by email, to Discord or to a webhook
+
+use anyhow::{bail, Context};
+use futures::future::BoxFuture;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use serde::Deserialize;
+
+/// Something worth telling about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    /// A one-line summary, like `3 tracks of Demos are unreachable`
+    pub subject: String,
+    /// The details, like the failures by host
+    pub body: String,
+}
+
+/// A way to deliver notifications
+pub trait Notifier: Send + Sync {
+    /// The name of the backend, for error messages
+    fn name(&self) -> &str;
+
+    /// Deliver `notification`
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+/// A notifier as configured in the `[[notify]]` tables of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Backend {
+    /// Send an email over SMTP
+    Email(Email),
+    /// Post to a Discord channel webhook
+    Discord(Discord),
+    /// Post the notification as JSON to any URL
+    Webhook(Webhook),
+}
+
+impl Backend {
+    /// The notifier of this backend
+    pub fn notifier(&self) -> &dyn Notifier {
+        match self {
+            Backend::Email(email) => email,
+            Backend::Discord(discord) => discord,
+            Backend::Webhook(webhook) => webhook,
+        }
+    }
+}
+
+/// Send `notification` with all `backends`, logging failures instead of returning them
+///
+/// A failed notification should not fail the check or deploy it is about.
+pub async fn notify_all(backends: &[Backend], notification: &Notification) {
+    let deliveries = backends.iter().map(|backend| async move {
+        let notifier = backend.notifier();
+        if let Err(e) = notifier.notify(notification).await {
+            tracing::error!("Failed to notify via {}: {e:#}", notifier.name());
+        }
+    });
+    futures::future::join_all(deliveries).await;
+}
+
+/// Email over SMTP with STARTTLS, or implicit TLS on port 465
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Email {
+    /// The SMTP server, like `smtp.example.org`
+    pub server: String,
+    /// The SMTP port, 587 by default
+    pub port: Option<u16>,
+    /// The SMTP user name, if the server requires authentication
+    pub username: Option<String>,
+    /// The environment variable holding the SMTP password
+    #[serde(default = "Email::default_password_var")]
+    pub password_var: String,
+    /// The sender address, like `Trackinator <bot@example.org>`
+    pub from: String,
+    /// The recipient addresses
+    pub to: Vec<String>,
+}
+
+impl Email {
+    fn default_password_var() -> String {
+        "TRACKINATOR_SMTP_PASSWORD".to_string()
+    }
+
+    async fn send(&self, notification: &Notification) -> anyhow::Result<()> {
+        let mut message = Message::builder()
+            .from(parse_mailbox(&self.from)?)
+            .subject(&notification.subject);
+        for to in &self.to {
+            message = message.to(parse_mailbox(to)?);
+        }
+        let message = message
+            .body(notification.body.clone())
+            .context("Failed to build email")?;
+
+        let port = self.port.unwrap_or(587);
+        let mut transport = if port == 465 {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&self.server)
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.server)
+        }
+        .with_context(|| format!("Invalid SMTP server {}", self.server))?
+        .port(port);
+        if let Some(username) = &self.username {
+            let password = std::env::var(&self.password_var)
+                .with_context(|| format!("No SMTP password in ${}", self.password_var))?;
+            transport = transport.credentials(Credentials::new(username.clone(), password));
+        }
+        transport
+            .build()
+            .send(message)
+            .await
+            .with_context(|| format!("Failed to send email via {}", self.server))?;
+        Ok(())
+    }
+}
+
+impl Notifier for Email {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.send(notification))
+    }
+}
+
+fn parse_mailbox(address: &str) -> anyhow::Result<Mailbox> {
+    address
+        .parse()
+        .with_context(|| format!("Invalid email address {address}"))
+}
+
+/// A Discord channel webhook
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Discord {
+    /// The webhook URL, like `https://discord.com/api/webhooks/<id>/<token>`
+    pub url: String,
+}
+
+impl Notifier for Discord {
+    fn name(&self) -> &str {
+        "discord"
+    }
+
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, anyhow::Result<()>> {
+        // Discord rejects messages over 2000 characters
+        let mut content = format!("**{}**\n{}", notification.subject, notification.body);
+        if let Some((end, _)) = content.char_indices().nth(1990) {
+            content.truncate(end);
+            content.push('…');
+        }
+        Box::pin(post(&self.url, serde_json::json!({ "content": content })))
+    }
+}
+
+/// Any URL accepting a JSON `POST` of `{"subject": …, "body": …}`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Webhook {
+    pub url: String,
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, anyhow::Result<()>> {
+        let payload = serde_json::json!({
+            "subject": notification.subject,
+            "body": notification.body,
+        });
+        Box::pin(post(&self.url, payload))
+    }
+}
+
+async fn post(url: &str, payload: serde_json::Value) -> anyhow::Result<()> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(payload.to_string())
+        .send()
+        .await
+        .with_context(|| format!("Failed to post to {url}"))?;
+    if !response.status().is_success() {
+        bail!("{url} responded with {}", response.status());
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use anyhow::{bail, Context};
+use futures::StreamExt;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+    thread,
+};
+
+use crate::progress::Progress;
+
+/// How many files to probe at the same time by default: one per CPU
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(4, |jobs| jobs.get())
+}
+
+/// Run `probe` on each of `paths`, at most `jobs` at a time on blocking threads, with a
+/// progress bar labelled `label`
+///
+/// The results are in the order of `paths`.
+pub async fn probe_all<T, F>(
+    paths: Vec<PathBuf>,
+    jobs: usize,
+    label: &str,
+    probe: F,
+) -> anyhow::Result<Vec<anyhow::Result<T>>>
+where
+    T: Send + 'static,
+    F: Fn(&Path) -> anyhow::Result<T> + Send + Sync + 'static,
+{
+    let progress = Progress::new(paths.len(), label, "files")?;
+    let probe = Arc::new(probe);
+    let results = futures::stream::iter(paths)
+        .map(|path| {
+            let (probe, progress) = (probe.clone(), &progress);
+            async move {
+                let name = path.display().to_string();
+                let bytes = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+                let result = tokio::task::spawn_blocking(move || probe(&path))
+                    .await
+                    .context("Probing panicked")
+                    .and_then(|result| result);
+                match &result {
+                    Ok(_) => {
+                        progress.add_bytes(bytes);
+                        progress.done(name);
+                    }
+                    Err(_) => progress.done(format!("{name} failed")),
+                }
+                result
+            }
+        })
+        .buffered(jobs.max(1))
+        .collect()
+        .await;
+    progress.finish();
+    Ok(results)
+}
+
+/// The size in bytes of the file at `path`
+pub fn size(path: &Path) -> anyhow::Result<u64> {
+    let metadata =
+        std::fs::metadata(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(metadata.len())
+}
+
+/// The play time in seconds of the audio file at `path`, determined by `ffprobe`
+pub fn duration(path: &Path) -> anyhow::Result<f64> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(path)
+        .output()
+        .context("Failed to run ffprobe")?;
+    if !output.status.success() {
+        bail!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let duration = String::from_utf8_lossy(&output.stdout);
+    duration
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid duration {duration}"))
+}
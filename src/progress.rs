@@ -0,0 +1,157 @@
+//! Progress bars of long-running commands, and the timing summary printed when they finish
+
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+static BARS: AtomicBool = AtomicBool::new(true);
+static SUMMARIES: AtomicBool = AtomicBool::new(true);
+
+/// Whether to draw progress bars, else print a line per item, and whether to print the
+/// status lines and summaries at all
+///
+/// Bars are also not drawn if stderr is not a terminal.
+pub fn init(bars: bool, summaries: bool) {
+    BARS.store(bars, Ordering::Relaxed);
+    SUMMARIES.store(summaries, Ordering::Relaxed);
+}
+
+fn bars() -> bool {
+    BARS.load(Ordering::Relaxed)
+}
+
+fn summaries() -> bool {
+    SUMMARIES.load(Ordering::Relaxed)
+}
+
+/// A set of bars drawn together, hidden unless bars are enabled
+pub fn multi() -> MultiProgress {
+    if bars() {
+        MultiProgress::new()
+    } else {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    }
+}
+
+/// A bar of bytes, for a single transfer in `multi`
+pub fn bytes(multi: &MultiProgress, len: u64) -> anyhow::Result<ProgressBar> {
+    let bar = multi.add(ProgressBar::new(len));
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{prefix:>12} [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec}) {msg}",
+        )?
+        .progress_chars("=> "),
+    );
+    Ok(bar)
+}
+
+/// The progress through a number of items, like the URLs to check
+#[derive(Debug)]
+pub struct Progress {
+    bar: ProgressBar,
+    label: String,
+    unit: &'static str,
+    start: Instant,
+    bytes: AtomicU64,
+}
+
+impl Progress {
+    /// A bar over `len` items called `unit`, like `files`, labelled `label`, like `Hashing`
+    pub fn new(len: usize, label: &str, unit: &'static str) -> anyhow::Result<Self> {
+        Self::add_to(&multi(), len, label, unit)
+    }
+
+    /// Like [`new`](Self::new), drawn in `multi`
+    pub fn add_to(
+        multi: &MultiProgress,
+        len: usize,
+        label: &str,
+        unit: &'static str,
+    ) -> anyhow::Result<Self> {
+        let bar = multi.add(ProgressBar::new(len as u64));
+        bar.set_style(
+            ProgressStyle::with_template(&format!(
+                "{{prefix:>12}} [{{bar:30}}] {{pos}}/{{len}} {unit} {{wide_msg}}"
+            ))?
+            .progress_chars("=> "),
+        );
+        bar.set_prefix(label.to_string());
+        Ok(Self {
+            bar,
+            label: label.to_string(),
+            unit,
+            start: Instant::now(),
+            bytes: AtomicU64::new(0),
+        })
+    }
+
+    /// Show `item` as the one being worked on
+    pub fn start(&self, item: impl fmt::Display) {
+        self.bar.set_message(item.to_string());
+    }
+
+    /// Count an item as done, with `status` like `200 OK https://example.com/a.mp3`
+    ///
+    /// Without bars, the status is printed as a line of its own.
+    pub fn done(&self, status: impl fmt::Display) {
+        self.bar.inc(1);
+        if !bars() && summaries() {
+            eprintln!(
+                "{:>12} [{}/{}] {status}",
+                self.label,
+                self.bar.position(),
+                self.bar.length().unwrap_or(0)
+            );
+        } else {
+            self.bar.set_message(status.to_string());
+        }
+    }
+
+    /// Count `bytes` transferred or processed, for the throughput in the summary
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Remove the bar and print how many items were done in how long
+    pub fn finish(self) {
+        self.bar.finish_and_clear();
+        if summaries() {
+            eprintln!("{self}");
+        }
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let elapsed = self.start.elapsed();
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        let count = self.bar.position();
+        write!(
+            f,
+            "{:>12} {count} {} in {:.1?}, {:.1} {}/s",
+            self.label,
+            self.unit,
+            round(elapsed),
+            count as f64 / seconds,
+            self.unit
+        )?;
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        if bytes > 0 {
+            write!(
+                f,
+                ", {} at {}/s",
+                HumanBytes(bytes),
+                HumanBytes((bytes as f64 / seconds) as u64)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `duration` to the millisecond, for readable output
+fn round(duration: Duration) -> Duration {
+    Duration::from_millis(duration.as_millis() as u64)
+}
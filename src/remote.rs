@@ -0,0 +1,117 @@
+//! Manifests hosted on a web server next to the audio, read with `GET` and written back with
+//! `PUT`, like on WebDAV shares
+
+use anyhow::{bail, Context};
+use reqwest::{
+    header::{HeaderValue, CONTENT_TYPE, ETAG, IF_MATCH},
+    RequestBuilder, StatusCode,
+};
+use std::path::Path;
+
+use crate::manifest::{Manifest, Storage};
+
+/// The environment variable holding the user name for remote manifests
+pub const USER_VAR: &str = "TRACKINATOR_REMOTE_USER";
+
+/// The environment variable holding the password of [`USER_VAR`]
+pub const PASSWORD_VAR: &str = "TRACKINATOR_REMOTE_PASSWORD";
+
+/// The environment variable holding a bearer token for remote manifests, instead of a user
+pub const TOKEN_VAR: &str = "TRACKINATOR_REMOTE_TOKEN";
+
+/// The URL of the remote manifest `path`, if it is an `http` or `https` URL
+pub fn url(path: &Path) -> Option<&str> {
+    path.to_str()
+        .filter(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// A manifest read from a URL, remembering what it was for the write-back
+#[derive(Debug, Clone)]
+pub struct Remote {
+    url: String,
+    /// The entity tag of the manifest as read, to detect concurrent changes
+    etag: Option<HeaderValue>,
+    contents: Vec<u8>,
+}
+
+impl Remote {
+    /// Read the manifest at `url`, rejecting unknown fields with `strict`
+    pub async fn fetch(url: &str, strict: bool) -> anyhow::Result<(Self, Manifest, Storage)> {
+        let response = authorize(reqwest::Client::new().get(url))
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch manifest {url}"))?;
+        if !response.status().is_success() {
+            bail!("Failed to fetch manifest {url}: {}", response.status());
+        }
+        let etag = response.headers().get(ETAG).cloned();
+        let contents = response
+            .bytes()
+            .await
+            .with_context(|| format!("Failed to fetch manifest {url}"))?
+            .to_vec();
+        let (manifest, storage) = Manifest::parse(contents.clone(), strict)?;
+        let remote = Self {
+            url: url.to_string(),
+            etag,
+            contents,
+        };
+        Ok((remote, manifest, storage))
+    }
+
+    /// A unified diff from the manifest as read to `manifest`
+    pub fn diff(&self, manifest: &Manifest) -> anyhow::Result<String> {
+        manifest.diff_from(&self.contents, &self.url)
+    }
+
+    /// Write the manifest as read to `<url>.bak`
+    pub async fn backup(&self) -> anyhow::Result<()> {
+        let url = format!("{}.bak", self.url);
+        put(&url, self.contents.clone(), None)
+            .await
+            .context("Failed to back up manifest")
+    }
+
+    /// Replace the remote manifest by `manifest`, unless it changed since it was read
+    pub async fn publish(&self, manifest: &Manifest, storage: Storage) -> anyhow::Result<()> {
+        put(&self.url, manifest.to_bytes(storage)?, self.etag.as_ref()).await
+    }
+}
+
+/// `PUT` `contents` to `url`, if its entity tag still is `etag`
+async fn put(url: &str, contents: Vec<u8>, etag: Option<&HeaderValue>) -> anyhow::Result<()> {
+    let content_type = match contents.trim_ascii_start().starts_with(b"{") {
+        true => "application/json",
+        false => "text/plain",
+    };
+    let mut request = reqwest::Client::new()
+        .put(url)
+        .header(CONTENT_TYPE, content_type)
+        .body(contents);
+    if let Some(etag) = etag {
+        request = request.header(IF_MATCH, etag);
+    }
+    let response = authorize(request)
+        .send()
+        .await
+        .with_context(|| format!("Failed to write manifest {url}"))?;
+    match response.status() {
+        StatusCode::PRECONDITION_FAILED => {
+            bail!("Manifest {url} was changed by someone else since it was read, try again")
+        }
+        status if !status.is_success() => bail!("Failed to write manifest {url}: {status}"),
+        _ => Ok(()),
+    }
+}
+
+/// `request` with the credentials from the environment, if any
+fn authorize(request: RequestBuilder) -> RequestBuilder {
+    let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+    if let Some(token) = var(TOKEN_VAR) {
+        request.bearer_auth(token)
+    } else if let Some(user) = var(USER_VAR) {
+        request.basic_auth(user, var(PASSWORD_VAR))
+    } else {
+        request
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{hash::hex, manifest::PATH_SEGMENT};
+use anyhow::{bail, Context};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use percent_encoding::utf8_percent_encode;
+use sha2::{Digest, Sha256};
+
+/// The longest expiry of a presigned URL that S3 accepts, in seconds
+pub const MAX_EXPIRY: u64 = 7 * 24 * 60 * 60;
+
+/// AWS credentials and location, read from the usual environment variables
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    region: String,
+    /// A custom endpoint like `https://minio.local:9000`, addressed path-style
+    endpoint: Option<String>,
+}
+
+impl Credentials {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        Ok(Self {
+            access_key: var("AWS_ACCESS_KEY_ID").context("AWS_ACCESS_KEY_ID is not set")?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY").context("AWS_SECRET_ACCESS_KEY is not set")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+            region: var("AWS_REGION")
+                .or_else(|| var("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|| "us-east-1".to_string()),
+            endpoint: var("AWS_ENDPOINT_URL").map(|e| e.trim_end_matches('/').to_string()),
+        })
+    }
+}
+
+/// Whether `url` is an `s3://bucket/key` URL
+pub fn is_s3(url: &str) -> bool {
+    url.starts_with("s3://")
+}
+
+/// Presign the `s3://bucket/key` `url` for `method`, valid for `expiry` seconds
+pub fn presign(
+    url: &str,
+    method: &str,
+    expiry: u64,
+    credentials: &Credentials,
+) -> anyhow::Result<String> {
+    presign_at(url, method, expiry, credentials, Utc::now())
+}
+
+/// Presign with AWS Signature Version 4 query parameters, as of `now`
+fn presign_at(
+    url: &str,
+    method: &str,
+    expiry: u64,
+    credentials: &Credentials,
+    now: DateTime<Utc>,
+) -> anyhow::Result<String> {
+    if expiry == 0 || expiry > MAX_EXPIRY {
+        bail!("Presigned URL expiry must be between 1 and {MAX_EXPIRY} seconds");
+    }
+    let (bucket, key) = url
+        .strip_prefix("s3://")
+        .and_then(|rest| rest.split_once('/'))
+        .with_context(|| format!("Invalid S3 URL {url}"))?;
+    if bucket.is_empty() || key.is_empty() {
+        bail!("Invalid S3 URL {url}");
+    }
+
+    let (base, host, path) = match &credentials.endpoint {
+        Some(endpoint) => {
+            let host = endpoint
+                .split_once("://")
+                .map_or(endpoint.as_str(), |(_, host)| host);
+            (
+                endpoint.clone(),
+                host.to_string(),
+                format!("/{bucket}/{key}"),
+            )
+        }
+        None => {
+            let host = format!("{bucket}.s3.{}.amazonaws.com", credentials.region);
+            (format!("https://{host}"), host, format!("/{key}"))
+        }
+    };
+
+    let date = now.format("%Y%m%d").to_string();
+    let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let scope = format!("{date}/{}/s3/aws4_request", credentials.region);
+
+    let mut query = vec![
+        ("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_string()),
+        (
+            "X-Amz-Credential",
+            format!("{}/{scope}", credentials.access_key),
+        ),
+        ("X-Amz-Date", timestamp.clone()),
+        ("X-Amz-Expires", expiry.to_string()),
+        ("X-Amz-SignedHeaders", "host".to_string()),
+    ];
+    if let Some(token) = &credentials.session_token {
+        query.push(("X-Amz-Security-Token", token.clone()));
+    }
+    query.sort();
+    let query = query
+        .iter()
+        .map(|(name, value)| format!("{name}={}", utf8_percent_encode(value, PATH_SEGMENT)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let canonical_request =
+        format!("{method}\n{path}\n{query}\nhost:{host}\n\nhost\nUNSIGNED-PAYLOAD");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let mut key = format!("AWS4{}", credentials.secret_key).into_bytes();
+    for part in [date.as_str(), &credentials.region, "s3", "aws4_request"] {
+        key = hmac(&key, part.as_bytes());
+    }
+    let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+    Ok(format!("{base}{path}?{query}&X-Amz-Signature={signature}"))
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
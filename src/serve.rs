@@ -0,0 +1,464 @@
+//! A server for the page, an M3U playlist and direct streams of the songs
+
+use anyhow::{bail, Context};
+use bytes::Bytes;
+use chrono::Utc;
+use futures::{Stream, StreamExt};
+use hyper::{
+    header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, HOST, LOCATION, RANGE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use std::{
+    collections::hash_map::DefaultHasher,
+    convert::Infallible,
+    fmt::Write,
+    hash::{Hash, Hasher},
+    io::SeekFrom,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    time::Instant,
+};
+use tokio_util::io::ReaderStream;
+use url::Url;
+
+use crate::{
+    check::check_url,
+    generate::{Generator, Html},
+    manifest::{song_url, Manifest, Song, PATH_SEGMENT},
+    s3,
+};
+
+/// Reloads the page once the watched files change, or overlays the error if the page fails
+///
+/// `__VERSION__` and `__ERROR__` are replaced by the version served and the error, if any.
+const RELOAD_SCRIPT: &str = r#"
+(function () {
+  var version = __VERSION__;
+  function overlay(error) {
+    var element = document.getElementById("trackinator-error");
+    if (!element) {
+      element = document.createElement("pre");
+      element.id = "trackinator-error";
+      element.style.cssText = "position: fixed; inset: 0; margin: 0; padding: 2em; " +
+        "overflow: auto; z-index: 1000; background: rgba(30, 0, 0, 0.92); color: #ffb4b4; " +
+        "font: 14px monospace; white-space: pre-wrap;";
+      document.body.appendChild(element);
+    }
+    element.textContent = error;
+  }
+  function poll() {
+    fetch("/__reload?since=" + encodeURIComponent(version))
+      .then(function (response) { return response.json(); })
+      .then(function (state) {
+        if (state.version === version) return;
+        if (!state.error) return location.reload();
+        version = state.version;
+        overlay(state.error);
+      })
+      .catch(function () {})
+      .then(function () { setTimeout(poll, 1000); });
+  }
+  var error = __ERROR__;
+  if (error) overlay(error);
+  poll();
+})();
+"#;
+
+/// How long redirects to presigned `s3://` URLs stay valid, in seconds
+const S3_EXPIRY: u64 = 60 * 60;
+
+/// Serve the manifest at `manifest` on `address` until the process is stopped
+///
+/// * `/` is the HTML page, playing from the stream endpoints
+/// * `/playlist.m3u8` lists the stream endpoints of all published songs
+/// * `/stream/<slug>` streams a local song, or redirects to a remote one
+///
+/// The manifest is read anew for each request, so edits show up without a restart. The page
+/// reloads itself when the manifest, its stylesheet or lyrics change, and shows errors in an
+/// overlay until they are fixed.
+///
+/// With `throttle`, streams are sent no faster than that rate, to preview slow connections.
+pub async fn serve(
+    manifest: PathBuf,
+    address: SocketAddr,
+    throttle: Option<Rate>,
+) -> anyhow::Result<()> {
+    let base = std::env::current_dir()
+        .context("Failed to get current directory")?
+        .join(manifest.parent().unwrap_or(Path::new("")));
+    let state = Arc::new(State {
+        manifest,
+        base,
+        throttle,
+    });
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(state.respond(request).await) }
+            }))
+        }
+    });
+    let server = hyper::Server::try_bind(&address)
+        .with_context(|| format!("Failed to bind {address}"))?
+        .serve(make_service);
+    println!("Serving on http://{}", server.local_addr());
+    server.await.context("Server failed")
+}
+
+struct State {
+    manifest: PathBuf,
+    /// The directory local songs are resolved relative to
+    base: PathBuf,
+    throttle: Option<Rate>,
+}
+
+impl State {
+    async fn respond(&self, request: Request<Body>) -> Response<Body> {
+        tracing::debug!(method = %request.method(), uri = %request.uri(), "Request");
+        if !matches!(*request.method(), Method::GET | Method::HEAD) {
+            return status(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        match self.route(&request).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::error!("Failed to serve {}: {e:#}", request.uri());
+                let mut response = Response::new(Body::from(format!("{e:#}\n")));
+                *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                response
+            }
+        }
+    }
+
+    async fn route(&self, request: &Request<Body>) -> anyhow::Result<Response<Body>> {
+        let manifest = self.published()?;
+        let slugs = manifest.slugs();
+        let path = request.uri().path();
+        if path == "/" || path == "/index.html" {
+            let version = self.version();
+            let response = Response::builder().header(CONTENT_TYPE, "text/html; charset=utf-8");
+            return Ok(match self.page(&manifest, &version) {
+                Ok(page) => response.body(page.into())?,
+                Err(e) => {
+                    tracing::warn!("Failed to generate page: {e:#}");
+                    response
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(error_page(&version, &e).into())?
+                }
+            });
+        }
+        if path == "/__reload" {
+            let version = self.version();
+            let since = request
+                .uri()
+                .query()
+                .and_then(|query| query.strip_prefix("since="));
+            let error = if since == Some(version.as_str()) {
+                None
+            } else {
+                self.page(&manifest, &version)
+                    .err()
+                    .map(|e| format!("{e:#}"))
+            };
+            let state = serde_json::json!({ "version": version, "error": error });
+            return Ok(Response::builder()
+                .header(CONTENT_TYPE, "application/json")
+                .body(state.to_string().into())?);
+        }
+        if path == "/playlist.m3u8" {
+            let host = request
+                .headers()
+                .get(HOST)
+                .and_then(|host| host.to_str().ok())
+                .context("Request has no Host header")?;
+            return Ok(Response::builder()
+                .header(CONTENT_TYPE, "audio/x-mpegurl; charset=utf-8")
+                .body(playlist(&manifest, &slugs, &format!("http://{host}")).into())?);
+        }
+        let Some(slug) = path.strip_prefix("/stream/") else {
+            return Ok(status(StatusCode::NOT_FOUND));
+        };
+        let Ok(slug) = percent_decode_str(slug).decode_utf8() else {
+            return Ok(status(StatusCode::NOT_FOUND));
+        };
+        let Some(index) = slugs.iter().position(|s| *s == slug) else {
+            return Ok(status(StatusCode::NOT_FOUND));
+        };
+        let song = &manifest.songs[index];
+        let url = check_url(song_url(&manifest, song)?, &self.base)?;
+        let file = Url::parse(&url)
+            .ok()
+            .filter(|url| url.scheme() == "file")
+            .and_then(|url| url.to_file_path().ok());
+        if let Some(file) = file {
+            if !file.is_file() {
+                tracing::warn!("Missing file {} of {}", file.display(), song.name);
+                return Ok(status(StatusCode::NOT_FOUND));
+            }
+            let range = request.headers().get(RANGE).and_then(|r| r.to_str().ok());
+            return stream(&file, manifest.mime_of(song), range, self.throttle).await;
+        }
+        let location = if s3::is_s3(&url) {
+            s3::presign(&url, "GET", S3_EXPIRY, &s3::Credentials::from_env()?)?
+        } else {
+            url
+        };
+        Ok(Response::builder()
+            .status(StatusCode::FOUND)
+            .header(LOCATION, location)
+            .body(Body::empty())?)
+    }
+
+    /// The page of `manifest`, playing from the stream endpoints and reloading after `version`
+    fn page(&self, manifest: &Manifest, version: &str) -> anyhow::Result<Vec<u8>> {
+        let urls = manifest
+            .slugs()
+            .iter()
+            .map(|slug| stream_path(slug))
+            .collect::<Vec<_>>();
+        let page = Html::default()
+            .base(&self.base)
+            .script(reload_script(version, None))
+            .generate(manifest, &urls)?;
+        let page = page.into_iter().next().context("No page generated")?;
+        page.contents.into_bytes()
+    }
+
+    /// A fingerprint of the modification times of the manifest and the files it includes
+    fn version(&self) -> String {
+        let mut watched = vec![self.manifest.clone()];
+        if let Ok(manifest) = Manifest::read(&self.manifest) {
+            let included = manifest
+                .stylesheet
+                .as_deref()
+                .into_iter()
+                .chain(manifest.songs.iter().flat_map(Song::files));
+            watched.extend(included.map(|path| self.base.join(path)));
+        }
+        let mut hasher = DefaultHasher::new();
+        for path in watched {
+            let metadata = std::fs::metadata(&path).ok();
+            path.hash(&mut hasher);
+            metadata
+                .as_ref()
+                .map(|metadata| (metadata.modified().ok(), metadata.len()))
+                .hash(&mut hasher);
+        }
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// The manifest with only the songs published as of now
+    fn published(&self) -> anyhow::Result<Manifest> {
+        let mut manifest = Manifest::read(&self.manifest)?;
+        let now = Utc::now();
+        manifest.songs.retain(|song| song.is_published(now));
+        Ok(manifest)
+    }
+}
+
+/// The live reload script for a page of `version`, overlaying `error` from the start
+fn reload_script(version: &str, error: Option<&str>) -> String {
+    // Keep `</script>` in the error from ending the script early
+    let json = |value: serde_json::Value| value.to_string().replace("</", "<\\/");
+    RELOAD_SCRIPT
+        .replace("__VERSION__", &json(version.into()))
+        .replace("__ERROR__", &json(error.into()))
+}
+
+/// A page showing `error`, reloading once the watched files change after `version`
+fn error_page(version: &str, error: &anyhow::Error) -> String {
+    let error = format!("{error:#}");
+    laby::render!(laby::html!(
+        laby::head!(
+            laby::meta!(charset = "utf-8"),
+            laby::title!("Failed to generate page")
+        ),
+        laby::body!(laby::script!(laby::raw!(reload_script(
+            version,
+            Some(&error)
+        ))))
+    ))
+}
+
+/// The path of the stream endpoint of the song with `slug`, percent-encoded as slugs keep
+/// non-ASCII letters
+pub fn stream_path(slug: &str) -> String {
+    format!("/stream/{}", utf8_percent_encode(slug, PATH_SEGMENT))
+}
+
+/// An extended M3U playlist of the stream endpoints below `origin`
+fn playlist(manifest: &Manifest, slugs: &[String], origin: &str) -> String {
+    let mut playlist = String::from("#EXTM3U\n");
+    if !manifest.title.is_empty() {
+        let _ = writeln!(playlist, "#PLAYLIST:{}", manifest.title);
+    }
+    let names = manifest.display_names();
+    for ((song, name), slug) in manifest.songs.iter().zip(names).zip(slugs) {
+        let duration = song.duration.map_or(-1, |duration| duration.round() as i64);
+        let _ = writeln!(playlist, "#EXTINF:{duration},{name}");
+        let _ = writeln!(playlist, "{origin}{}", stream_path(slug));
+    }
+    playlist
+}
+
+/// Stream the local file at `path`, honoring a single byte `range`
+async fn stream(
+    path: &Path,
+    mime: Option<&str>,
+    range: Option<&str>,
+    throttle: Option<Rate>,
+) -> anyhow::Result<Response<Body>> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let size = file.metadata().await?.len();
+    let mut response = Response::builder()
+        .header(ACCEPT_RANGES, "bytes")
+        .header(CONTENT_TYPE, mime.unwrap_or("application/octet-stream"));
+    let (start, length) = match range.and_then(|range| parse_range(range, size)) {
+        Some((start, end)) => {
+            response = response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(CONTENT_RANGE, format!("bytes {start}-{end}/{size}"));
+            (start, end - start + 1)
+        }
+        None => (0, size),
+    };
+    file.seek(SeekFrom::Start(start)).await?;
+    let chunks = ReaderStream::new(file.take(length));
+    let body = match throttle {
+        Some(rate) => Body::wrap_stream(throttled(chunks, rate)),
+        None => Body::wrap_stream(chunks),
+    };
+    Ok(response.header(CONTENT_LENGTH, length).body(body)?)
+}
+
+/// A transfer rate, parsed from bits per second like `256kbps`, `1.5mbps` or `9600bps`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    bytes_per_second: f64,
+}
+
+impl Rate {
+    pub fn bytes_per_second(self) -> f64 {
+        self.bytes_per_second
+    }
+}
+
+impl FromStr for Rate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_ascii_lowercase();
+        let (number, factor) = [("kbps", 1e3), ("mbps", 1e6), ("gbps", 1e9), ("bps", 1.0)]
+            .into_iter()
+            .find_map(|(unit, factor)| Some((lower.strip_suffix(unit)?, factor)))
+            .with_context(|| format!("Rate {s} needs a unit: bps, kbps, mbps or gbps"))?;
+        let number: f64 = number
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid rate {s}"))?;
+        if !(number > 0.0 && number.is_finite()) {
+            bail!("Rate {s} must be positive");
+        }
+        Ok(Self {
+            bytes_per_second: number * factor / 8.0,
+        })
+    }
+}
+
+/// `chunks`, delayed so that they arrive no faster than `rate` on average
+fn throttled<S>(chunks: S, rate: Rate) -> impl Stream<Item = S::Item>
+where
+    S: Stream<Item = std::io::Result<Bytes>> + Unpin,
+{
+    let start = Instant::now();
+    futures::stream::unfold((chunks, 0usize), move |(mut chunks, sent)| async move {
+        let chunk = chunks.next().await?;
+        let sent = sent + chunk.as_ref().map_or(0, Bytes::len);
+        let due = start + Duration::from_secs_f64(sent as f64 / rate.bytes_per_second);
+        tokio::time::sleep_until(due).await;
+        Some((chunk, (chunks, sent)))
+    })
+}
+
+/// The inclusive byte range of a `Range: bytes=…` header within `size` bytes, if satisfiable
+fn parse_range(range: &str, size: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let last = size.checked_sub(1)?;
+    let (start, end) = if start.is_empty() {
+        let suffix: u64 = end.parse().ok()?;
+        (size.saturating_sub(suffix), last)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = match end {
+            "" => last,
+            end => end.parse::<u64>().ok()?.min(last),
+        };
+        (start, end)
+    };
+    (start <= end).then_some((start, end))
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::from(format!("{status}\n")));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates() {
+        let rate = |s: &str| s.parse::<Rate>().map(Rate::bytes_per_second);
+        assert_eq!(rate("9600bps").unwrap(), 1200.0);
+        assert_eq!(rate("256kbps").unwrap(), 32_000.0);
+        assert_eq!(rate(" 1.5 MBps ").unwrap(), 187_500.0);
+        assert_eq!(rate("1gbps").unwrap(), 125_000_000.0);
+    }
+
+    #[test]
+    fn invalid_rates() {
+        for rate in [
+            "256", "256kb", "kbps", "0kbps", "-1kbps", "infbps", "NaNbps",
+        ] {
+            assert!(rate.parse::<Rate>().is_err(), "{rate}");
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("bytes=0-0", 10), Some((0, 0)));
+        assert_eq!(parse_range("bytes=2-5", 10), Some((2, 5)));
+        assert_eq!(parse_range("bytes=4-", 10), Some((4, 9)));
+        assert_eq!(parse_range("bytes=4-100", 10), Some((4, 9)));
+    }
+
+    #[test]
+    fn suffix_ranges() {
+        assert_eq!(parse_range("bytes=-3", 10), Some((7, 9)));
+        assert_eq!(parse_range("bytes=-100", 10), Some((0, 9)));
+        assert_eq!(parse_range("bytes=-0", 10), None);
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(parse_range("bytes=10-", 10), None);
+        assert_eq!(parse_range("bytes=5-2", 10), None);
+        assert_eq!(parse_range("bytes=0-0", 0), None);
+        assert_eq!(parse_range("bytes=a-b", 10), None);
+        assert_eq!(parse_range("items=0-1", 10), None);
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), None);
+    }
+}
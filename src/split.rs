@@ -0,0 +1,208 @@
+//! Partitioning a manifest into several, the inverse of [`merge`](crate::merge)
+
+use anyhow::{bail, Context};
+use std::{collections::BTreeMap, path::Path, str::FromStr};
+
+use crate::{
+    manifest::{slugify, Manifest},
+    probe,
+};
+
+/// How to group the songs of a manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum By {
+    /// One manifest per album, and one for the songs without
+    Album,
+    /// One manifest per tag, and one for the untagged songs; songs with several tags are in
+    /// several manifests
+    Tag,
+    /// Consecutive songs up to a maximum total size per manifest
+    Size,
+}
+
+impl FromStr for By {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "album" => Ok(By::Album),
+            "tag" => Ok(By::Tag),
+            "size" => Ok(By::Size),
+            _ => bail!("Unknown grouping {s}, expected one of album, tag, size"),
+        }
+    }
+}
+
+/// A group of songs split off a manifest
+#[derive(Debug, Clone)]
+pub struct Part {
+    /// The slug of the group, like `summer-tour` or `part-2`
+    pub name: String,
+    pub manifest: Manifest,
+}
+
+/// Split `manifest` into parts `by` album, tag or size, in the order the groups first appear
+///
+/// With [`By::Size`] the parts are at most `max_size` bytes, unless a single song is larger.
+/// Sizes not recorded in the manifest are taken from the local files below `base`. Each part
+/// keeps the settings of `manifest` and the playlists entries of its songs, and its title is
+/// suffixed with the group.
+pub fn split(
+    manifest: &Manifest,
+    by: By,
+    max_size: Option<u64>,
+    base: &Path,
+) -> anyhow::Result<Vec<Part>> {
+    // The indices of the songs of each group
+    let mut groups = Vec::<(String, Vec<usize>)>::new();
+    let mut add = |group: &str, index: usize| match groups.iter_mut().find(|(g, _)| g == group) {
+        Some((_, songs)) => songs.push(index),
+        None => groups.push((group.to_string(), vec![index])),
+    };
+    match by {
+        By::Album => {
+            for (index, song) in manifest.songs.iter().enumerate() {
+                add(song.album.as_deref().unwrap_or("No album"), index);
+            }
+        }
+        By::Tag => {
+            for (index, song) in manifest.songs.iter().enumerate() {
+                if song.tags.is_empty() {
+                    add("Untagged", index);
+                }
+                for tag in &song.tags {
+                    add(tag, index);
+                }
+            }
+        }
+        By::Size => {
+            let max_size = max_size.context("Splitting by size needs a maximum size")?;
+            let (mut part, mut total) = (1, 0);
+            for (index, song) in manifest.songs.iter().enumerate() {
+                let size = song
+                    .size
+                    .or_else(|| probe::size(&base.join(&song.path)).ok())
+                    .with_context(|| {
+                        format!(
+                            "Size of {} unknown, probe it with generate --probe",
+                            song.name
+                        )
+                    })?;
+                if total > 0 && total + size > max_size {
+                    part += 1;
+                    total = 0;
+                }
+                total += size;
+                add(&format!("Part {part}"), index);
+            }
+        }
+    }
+
+    let ids = manifest.slugs();
+    Ok(groups
+        .into_iter()
+        .map(|(group, indices)| {
+            let mut part = Manifest {
+                title: format!("{} – {group}", manifest.title),
+                playlists: BTreeMap::new(),
+                songs: indices
+                    .iter()
+                    .map(|&index| manifest.songs[index].clone())
+                    .collect(),
+                ..manifest.clone()
+            };
+            // Songs sharing a name may get other IDs among fewer songs
+            let renames = indices
+                .iter()
+                .zip(part.slugs())
+                .map(|(&index, id)| (ids[index].as_str(), id))
+                .collect::<BTreeMap<_, _>>();
+            part.playlists = manifest
+                .playlists
+                .iter()
+                .map(|(name, entries)| {
+                    let entries = entries
+                        .iter()
+                        .filter_map(|id| renames.get(id.as_str()).cloned())
+                        .collect::<Vec<_>>();
+                    (name.clone(), entries)
+                })
+                .filter(|(_, entries)| !entries.is_empty())
+                .collect();
+            Part {
+                name: slugify(&group),
+                manifest: part,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Song;
+
+    fn song(name: &str, album: Option<&str>, tags: &[&str], size: u64) -> Song {
+        Song {
+            name: name.to_string(),
+            album: album.map(str::to_string),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            size: Some(size),
+            ..Song::default()
+        }
+    }
+
+    fn manifest() -> Manifest {
+        Manifest {
+            title: "Tour".to_string(),
+            songs: vec![
+                song("A", Some("Studio"), &["live", "slow"], 40),
+                song("B", None, &[], 40),
+                song("A", Some("Live"), &["live"], 40),
+            ],
+            playlists: [("best".to_string(), vec!["a-2".to_string(), "b".to_string()])].into(),
+            ..Manifest::default()
+        }
+    }
+
+    #[test]
+    fn albums_rename_playlist_entries() {
+        let parts = split(&manifest(), By::Album, None, Path::new("")).unwrap();
+        let groups = parts
+            .iter()
+            .map(|part| part.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(groups, ["studio", "no-album", "live"]);
+        assert_eq!(parts[2].manifest.title, "Tour – Live");
+        assert_eq!(parts[2].manifest.slugs(), ["a"]);
+        assert_eq!(parts[2].manifest.playlists["best"], ["a"]);
+        assert!(parts[0].manifest.playlists.is_empty());
+        assert_eq!(parts[1].manifest.playlists["best"], ["b"]);
+    }
+
+    #[test]
+    fn tags_put_songs_in_several_parts() {
+        let parts = split(&manifest(), By::Tag, None, Path::new("")).unwrap();
+        let groups = parts
+            .iter()
+            .map(|part| part.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(groups, ["live", "slow", "untagged"]);
+        assert_eq!(parts[0].manifest.slugs(), ["a", "a-2"]);
+        assert_eq!(parts[0].manifest.playlists["best"], ["a-2"]);
+    }
+
+    #[test]
+    fn sizes_fill_parts_in_order() {
+        let parts = split(&manifest(), By::Size, Some(80), Path::new("")).unwrap();
+        let groups = parts
+            .iter()
+            .map(|part| part.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(groups, ["part-1", "part-2"]);
+        assert_eq!(parts[1].manifest.playlists["best"], ["a"]);
+        let parts = split(&manifest(), By::Size, Some(10), Path::new("")).unwrap();
+        assert_eq!(parts.len(), 3);
+        assert!(split(&manifest(), By::Size, None, Path::new("")).is_err());
+    }
+}
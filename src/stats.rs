@@ -0,0 +1,392 @@
+//! Statistics about the collection, and play and download counts per track imported from web
+//! server access logs
+
+use anyhow::Context;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    path::Path,
+    str::FromStr,
+};
+use url::Url;
+
+use crate::{
+    atomic,
+    generate::{format_duration, format_size},
+    manifest::{song_url, Manifest},
+    probe,
+    serve::stream_path,
+};
+
+/// The name of the file accumulating imported statistics, next to the manifest
+pub const STATS_FILE: &str = ".trackinator-stats.json";
+
+/// An overview of the songs of a manifest
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Summary {
+    pub tracks: usize,
+    /// The total size in bytes of the songs with known size
+    pub size: u64,
+    /// The songs of unknown size, neither probed nor local
+    pub unknown_size: usize,
+    /// The total play time in seconds of the probed songs
+    pub duration: f64,
+    /// The songs of unknown duration, not probed
+    pub unknown_duration: usize,
+    /// The number of songs per lowercase file extension
+    pub extensions: BTreeMap<String, usize>,
+    /// The number of songs per album
+    pub albums: BTreeMap<String, usize>,
+    /// The number of songs per tag
+    pub tags: BTreeMap<String, usize>,
+    /// The largest songs, largest first
+    pub largest: Vec<Largest>,
+}
+
+/// A song among the largest of a manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct Largest {
+    pub id: String,
+    pub name: String,
+    pub size: u64,
+}
+
+impl Summary {
+    /// Summarize `manifest`, listing its `largest` songs
+    ///
+    /// Sizes not recorded in the manifest are taken from the local files below `base`.
+    pub fn new(manifest: &Manifest, base: &Path, largest: usize) -> Self {
+        let mut summary = Self {
+            tracks: manifest.songs.len(),
+            ..Default::default()
+        };
+        let mut sizes = Vec::new();
+        for (song, id) in manifest.songs.iter().zip(manifest.slugs()) {
+            match song
+                .size
+                .or_else(|| probe::size(&base.join(&song.path)).ok())
+            {
+                Some(size) => {
+                    summary.size += size;
+                    sizes.push((size, song, id));
+                }
+                None => summary.unknown_size += 1,
+            }
+            match song.duration {
+                Some(duration) => summary.duration += duration,
+                None => summary.unknown_duration += 1,
+            }
+            let extension = song.path.extension().map_or(String::new(), |extension| {
+                extension.to_string_lossy().to_lowercase()
+            });
+            *summary.extensions.entry(extension).or_default() += 1;
+            if let Some(album) = &song.album {
+                *summary.albums.entry(album.clone()).or_default() += 1;
+            }
+            for tag in &song.tags {
+                *summary.tags.entry(tag.clone()).or_default() += 1;
+            }
+        }
+        sizes.sort_by_key(|(size, _, _)| std::cmp::Reverse(*size));
+        summary.largest = sizes
+            .into_iter()
+            .take(largest)
+            .map(|(size, song, id)| Largest {
+                id,
+                name: song.name.clone(),
+                size,
+            })
+            .collect();
+        summary
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unknown = |count: usize| match count {
+            0 => String::new(),
+            count => format!(" ({count} unknown)"),
+        };
+        writeln!(f, "{:<12}{}", "Tracks", self.tracks)?;
+        writeln!(
+            f,
+            "{:<12}{}{}",
+            "Size",
+            format_size(self.size),
+            unknown(self.unknown_size)
+        )?;
+        writeln!(
+            f,
+            "{:<12}{}{}",
+            "Duration",
+            format_duration(self.duration),
+            unknown(self.unknown_duration)
+        )?;
+        for (title, counts) in [
+            ("Extensions", &self.extensions),
+            ("Albums", &self.albums),
+            ("Tags", &self.tags),
+        ] {
+            if counts.is_empty() {
+                continue;
+            }
+            writeln!(f, "\n{title}")?;
+            for (name, count) in counts {
+                let name = if name.is_empty() { "(none)" } else { name };
+                writeln!(f, "  {count:>6}  {name}")?;
+            }
+        }
+        if !self.largest.is_empty() {
+            writeln!(f, "\nLargest")?;
+            for song in &self.largest {
+                writeln!(f, "  {:>9}  {}", format_size(song.size), song.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A request from an access log in Common or Combined Log Format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request<'a> {
+    pub client: &'a str,
+    pub time: DateTime<Utc>,
+    pub method: &'a str,
+    pub path: &'a str,
+    pub status: u16,
+}
+
+impl<'a> Request<'a> {
+    /// Parse a line like
+    /// `1.2.3.4 - - [10/Oct/2000:13:55:36 -0700] "GET /a.mp3 HTTP/1.1" 200 2326 "-" "curl"`
+    ///
+    /// The referrer and user agent of the Combined format are ignored.
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let (client, rest) = line.split_once(' ')?;
+        let (_, rest) = rest.split_once('[')?;
+        let (time, rest) = rest.split_once(']')?;
+        let time = DateTime::parse_from_str(time, "%d/%b/%Y:%H:%M:%S %z").ok()?;
+        let (_, rest) = rest.split_once('"')?;
+        let (request, rest) = rest.split_once('"')?;
+        let mut request = request.split(' ');
+        let (method, path) = (request.next()?, request.next()?);
+        let status = rest.split_whitespace().next()?.parse().ok()?;
+        Some(Self {
+            client,
+            time: time.with_timezone(&Utc),
+            method,
+            path,
+            status,
+        })
+    }
+}
+
+/// How often a track was played and downloaded
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Counts {
+    /// Listeners streaming the track, counted once per client and day
+    pub plays: u64,
+    /// Complete downloads of the track
+    pub downloads: u64,
+}
+
+impl std::ops::AddAssign for Counts {
+    fn add_assign(&mut self, other: Self) {
+        self.plays += other.plays;
+        self.downloads += other.downloads;
+    }
+}
+
+/// The periods counts are reported by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    /// The first day of the period containing `day`
+    fn start(self, day: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Day => day,
+            Period::Week => day - Duration::days(day.weekday().num_days_from_monday().into()),
+            Period::Month => day.with_day(1).unwrap_or(day),
+        }
+    }
+
+    /// The label of the period starting at `start`, like `2024-05-13` or `2024-05`
+    fn label(self, start: NaiveDate) -> String {
+        match self {
+            Period::Day | Period::Week => start.format("%Y-%m-%d").to_string(),
+            Period::Month => start.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Period::Day),
+            "week" => Ok(Period::Week),
+            "month" => Ok(Period::Month),
+            _ => anyhow::bail!("Unknown period {s}, expected one of day, week, month"),
+        }
+    }
+}
+
+/// The counts of all imported requests
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Stats {
+    /// The time of the last imported request per log file name, to skip them on re-import
+    #[serde(default)]
+    sources: BTreeMap<String, DateTime<Utc>>,
+    /// The counts per song ID and day
+    #[serde(default)]
+    tracks: BTreeMap<String, BTreeMap<NaiveDate, Counts>>,
+}
+
+/// What an import did
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Imported {
+    /// The requests attributed to a track
+    pub requests: usize,
+    /// The lines skipped as imported before
+    pub seen: usize,
+    /// The lines that are no access log entries
+    pub invalid: usize,
+}
+
+impl Stats {
+    /// The statistics at `path`, or empty ones if there are none yet
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .with_context(|| format!("Failed to read statistics {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to open {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let contents = serde_json::to_vec_pretty(self).context("Failed to serialize statistics")?;
+        atomic::write(path, contents)
+            .with_context(|| format!("Failed to write statistics {}", path.display()))
+    }
+
+    /// Count the requests for the songs of `manifest` in the access log `log`
+    ///
+    /// Requests are attributed to songs by the path of their URLs, also when deployed below a
+    /// subdirectory, and by the `/stream/<slug>` endpoints of `serve`. Partial responses (206)
+    /// count as plays, complete ones (200) as downloads.
+    pub fn import(&mut self, manifest: &Manifest, source: &str, log: &str) -> Imported {
+        let routes = routes(manifest);
+        let since = self.sources.get(source).copied();
+        let mut imported = Imported::default();
+        let mut listeners = HashSet::new();
+        let mut last = since;
+        for line in log.lines().filter(|line| !line.trim().is_empty()) {
+            let Some(request) = Request::parse(line) else {
+                imported.invalid += 1;
+                continue;
+            };
+            if since.is_some_and(|since| request.time <= since) {
+                imported.seen += 1;
+                continue;
+            }
+            last = last.max(Some(request.time));
+            if request.method != "GET" {
+                continue;
+            }
+            let path = request.path.split(['?', '#']).next().unwrap_or_default();
+            let Some(id) = route(&routes, path) else {
+                continue;
+            };
+            let day = request.time.date_naive();
+            let counts = match request.status {
+                206 if listeners.insert((request.client, id, day)) => Counts {
+                    plays: 1,
+                    downloads: 0,
+                },
+                200 => Counts {
+                    plays: 0,
+                    downloads: 1,
+                },
+                _ => continue,
+            };
+            imported.requests += 1;
+            *self
+                .tracks
+                .entry(id.to_string())
+                .or_default()
+                .entry(day)
+                .or_default() += counts;
+        }
+        if let Some(last) = last {
+            self.sources.insert(source.to_string(), last);
+        }
+        imported
+    }
+
+    /// The counts per period and song of `manifest`, as `(period, song ID, counts)` in order
+    pub fn report(&self, manifest: &Manifest, period: Period) -> Vec<(String, String, Counts)> {
+        let mut periods = BTreeMap::<NaiveDate, Vec<(String, Counts)>>::new();
+        for id in manifest.slugs() {
+            let Some(days) = self.tracks.get(&id) else {
+                continue;
+            };
+            let mut totals = BTreeMap::<NaiveDate, Counts>::new();
+            for (day, counts) in days {
+                *totals.entry(period.start(*day)).or_default() += *counts;
+            }
+            for (start, counts) in totals {
+                let songs = periods.entry(start).or_default();
+                if !songs.iter().any(|(other, _)| *other == id) {
+                    songs.push((id.clone(), counts));
+                }
+            }
+        }
+        periods
+            .into_iter()
+            .flat_map(|(start, songs)| {
+                let label = period.label(start);
+                songs
+                    .into_iter()
+                    .map(move |(id, counts)| (label.clone(), id, counts))
+            })
+            .collect()
+    }
+}
+
+/// The URL paths of the songs of `manifest`, with the ID of their song
+fn routes(manifest: &Manifest) -> Vec<(String, String)> {
+    let mut routes = Vec::new();
+    for (song, id) in manifest.songs.iter().zip(manifest.slugs()) {
+        if let Ok(url) = song_url(manifest, song) {
+            let path = match Url::parse(&url) {
+                Ok(url) => url.path().to_string(),
+                Err(_) => format!("/{}", url.trim_start_matches("./")),
+            };
+            routes.push((path, id.clone()));
+        }
+        routes.push((stream_path(&id), id));
+    }
+    routes
+}
+
+/// The ID of the song requested at `path`, preferring exact matches
+fn route<'a>(routes: &'a [(String, String)], path: &str) -> Option<&'a str> {
+    routes
+        .iter()
+        .find(|(route, _)| route == path)
+        .or_else(|| {
+            routes
+                .iter()
+                .find(|(route, _)| path.ends_with(route.as_str()))
+        })
+        .map(|(_, id)| id.as_str())
+}
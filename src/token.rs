@@ -0,0 +1,66 @@
+//! Access tokens appended to song URLs, for hosts that require them
+//!
+//! The manifest `url_suffix` is a template like `?token={env:TRACK_TOKEN}` with the
+//! placeholders
+//!
+//! - `{env:NAME}`: the value of the environment variable `NAME`, percent-encoded
+//! - `{hmac:NAME}`: the hex HMAC-SHA256 of the URL path, like `/music/a.mp3`, keyed with the
+//!   value of the environment variable `NAME`
+//! - `{path}`: the URL path, percent-encoded
+
+use anyhow::{bail, Context};
+use hmac::{Hmac, Mac};
+use percent_encoding::utf8_percent_encode;
+use sha2::Sha256;
+use url::Url;
+
+use crate::{hash::hex, manifest::PATH_SEGMENT};
+
+/// `url` with the suffix `template` appended, its placeholders filled in
+///
+/// Only `http` and `https` URLs get the suffix, others are returned as they are.
+pub fn append(url: String, template: &str) -> anyhow::Result<String> {
+    let Ok(parsed) = Url::parse(&url) else {
+        return Ok(url);
+    };
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Ok(url);
+    }
+    let mut suffixed = url.clone();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        suffixed.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .with_context(|| format!("Unclosed placeholder in URL suffix {template}"))?;
+        let placeholder = &rest[start + 1..start + end];
+        let value = match placeholder.split_once(':') {
+            Some(("env", name)) => {
+                let value = var(name)?;
+                utf8_percent_encode(&value, PATH_SEGMENT).to_string()
+            }
+            Some(("hmac", name)) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(var(name)?.as_bytes())
+                    .expect("HMAC accepts keys of any length");
+                mac.update(parsed.path().as_bytes());
+                hex(&mac.finalize().into_bytes())
+            }
+            None if placeholder == "path" => {
+                utf8_percent_encode(parsed.path(), PATH_SEGMENT).to_string()
+            }
+            _ => bail!(
+                "Unknown placeholder {{{placeholder}}} in URL suffix, expected {{env:NAME}}, \
+                 {{hmac:NAME}} or {{path}}"
+            ),
+        };
+        suffixed.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    suffixed.push_str(rest);
+    Ok(suffixed)
+}
+
+/// The value of the environment variable `name` used in the URL suffix
+fn var(name: &str) -> anyhow::Result<String> {
+    std::env::var(name).with_context(|| format!("The URL suffix needs ${name}"))
+}
@@ -0,0 +1,111 @@
+use anyhow::{bail, Context};
+use std::{
+    path::Path,
+    process::{self, Command},
+    str::FromStr,
+};
+
+/// A web-friendly audio format to transcode to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// Opus in Ogg, `.opus`
+    Opus,
+    /// MP3, `.mp3`
+    Mp3,
+    /// AAC in MP4, `.m4a`
+    Aac,
+    /// Vorbis in Ogg, `.ogg`
+    Vorbis,
+}
+
+impl Codec {
+    /// The file extension of transcoded files
+    pub fn extension(self) -> &'static str {
+        match self {
+            Codec::Opus => "opus",
+            Codec::Mp3 => "mp3",
+            Codec::Aac => "m4a",
+            Codec::Vorbis => "ogg",
+        }
+    }
+
+    fn encoder(self) -> &'static str {
+        match self {
+            Codec::Opus => "libopus",
+            Codec::Mp3 => "libmp3lame",
+            Codec::Aac => "aac",
+            Codec::Vorbis => "libvorbis",
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "opus" => Ok(Codec::Opus),
+            "mp3" => Ok(Codec::Mp3),
+            "aac" => Ok(Codec::Aac),
+            "vorbis" => Ok(Codec::Vorbis),
+            _ => bail!("Unknown codec {s}, expected one of opus, mp3, aac, vorbis"),
+        }
+    }
+}
+
+/// Whether `target` exists and is at least as new as `source`
+pub fn is_up_to_date(source: &Path, target: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    matches!((modified(source), modified(target)), (Some(source), Some(target)) if target >= source)
+}
+
+/// Transcode the audio file `source` into `target` with `codec` at `bitrate`, like `96k`, by
+/// running `ffmpeg`
+///
+/// Metadata tags are kept, embedded cover art and video streams are dropped. ffmpeg writes to
+/// a temporary file next to `target`, which replaces it once complete, so a failed run never
+/// leaves a truncated `target` that looks up to date.
+pub fn transcode(source: &Path, target: &Path, codec: Codec, bitrate: &str) -> anyhow::Result<()> {
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    // Keep the extension, ffmpeg picks the container by it
+    let name = target.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = target.extension().unwrap_or_default().to_string_lossy();
+    let temporary = target.with_file_name(format!(".{name}.{}.tmp.{extension}", process::id()));
+    let result = run_ffmpeg(source, &temporary, codec, bitrate).and_then(|()| {
+        std::fs::rename(&temporary, target)
+            .with_context(|| format!("Failed to move transcoded file to {}", target.display()))
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    result
+}
+
+fn run_ffmpeg(source: &Path, target: &Path, codec: Codec, bitrate: &str) -> anyhow::Result<()> {
+    let output = Command::new("ffmpeg")
+        .args(["-nostdin", "-hide_banner", "-loglevel", "error", "-y", "-i"])
+        .arg(source)
+        .args([
+            "-vn",
+            "-map_metadata",
+            "0",
+            "-c:a",
+            codec.encoder(),
+            "-b:a",
+            bitrate,
+        ])
+        .arg(target)
+        .output()
+        .context("Failed to run ffmpeg")?;
+    if !output.status.success() {
+        bail!(
+            "ffmpeg failed on {}: {}",
+            source.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}